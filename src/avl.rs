@@ -0,0 +1,289 @@
+use std::cmp::{self, Ordering};
+
+use balance::sealed::Sealed;
+use error::{InvariantViolation, Side, ViolationKind};
+use node;
+use Balance;
+use Link;
+use Node;
+
+/// Rebalances as an AVL tree: the heights of every node's two subtrees differ
+/// by at most one, so the tree's height stays logarithmic in its length.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Avl;
+
+impl Balance for Avl {}
+
+impl Sealed for Avl {
+    fn insert<T, F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+            where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+        let added = match *link {
+            Some(ref mut node) => match order(&value, &node.data) {
+                Ordering::Less => Avl::insert(&mut node.left, value, order, on_equal),
+                Ordering::Greater => Avl::insert(&mut node.right, value, order, on_equal)
+                    .map(|rank| rank + node::size(&node.left) + 1),
+                Ordering::Equal => {
+                    on_equal(&mut node.data, value);
+                    None
+                }
+            },
+            None => {
+                let mut node = Node::leaf(value);
+                update(&mut node);
+                *link = Some(Box::new(node));
+                return Some(0);
+            }
+        };
+
+        if added.is_some() {
+            rebalance_link(link);
+        }
+        added
+    }
+
+    fn remove<T, F>(link: &mut Link<T>, probe: &mut F) -> Option<T>
+            where F : FnMut(&Node<T>) -> Ordering {
+        let ordering = match *link {
+            Some(ref node) => probe(node),
+            None => return None
+        };
+
+        let removed = {
+            let node = link.as_mut().unwrap();
+            match ordering {
+                Ordering::Less => Avl::remove(&mut node.left, probe),
+                Ordering::Greater => Avl::remove(&mut node.right, probe),
+                Ordering::Equal if node.left.is_some() && node.right.is_some() => {
+                    let successor = remove_min(&mut node.right).unwrap();
+                    Some(::std::mem::replace(&mut node.data, successor))
+                },
+                Ordering::Equal => {
+                    // At most one child, which is a balanced leaf by itself.
+                    let mut node = link.take().unwrap();
+                    *link = node.left.take().or_else(|| node.right.take());
+                    return Some(node.data);
+                }
+            }
+        };
+
+        if removed.is_some() {
+            rebalance_link(link);
+        }
+        removed
+    }
+
+    fn build<T>(link: &mut Link<T>, _: usize) {
+        if let Some(ref mut node) = *link {
+            Avl::build(&mut node.left, 0);
+            Avl::build(&mut node.right, 0);
+            update(node);
+        }
+    }
+
+    fn join<T>(left: Link<T>, mut middle: Box<Node<T>>, right: Link<T>) -> Box<Node<T>> {
+        // Descends the taller tree's inner edge to a subtree no more than one
+        // taller than the shorter tree, joins there, and rebalances on the
+        // way back up. Each level grows by at most one, so a single rotation
+        // or double rotation per level is enough.
+        let (left_height, right_height) = (height(&left), height(&right));
+        if left_height > right_height + 1 {
+            let mut node = left.unwrap();
+            node.right = Some(Avl::join(node.right.take(), middle, right));
+            rebalance(node)
+        } else if right_height > left_height + 1 {
+            let mut node = right.unwrap();
+            node.left = Some(Avl::join(left, middle, node.left.take()));
+            rebalance(node)
+        } else {
+            middle.left = left;
+            middle.right = right;
+            update(&mut middle);
+            middle
+        }
+    }
+
+    fn validate<T>(link: &Link<T>, path: &mut Vec<Side>) -> Result<(), InvariantViolation> {
+        check_heights(link, path).map(|_| ())
+    }
+}
+
+// Checks the cached heights and the balance of every node below `link`,
+// returning the height of the whole subtree.
+fn check_heights<T>(link: &Link<T>, path: &mut Vec<Side>) -> Result<u8, InvariantViolation> {
+    let node = match *link {
+        Some(ref node) => node,
+        None => return Ok(0)
+    };
+
+    path.push(Side::Left);
+    let left = check_heights(&node.left, path)?;
+    path.pop();
+    path.push(Side::Right);
+    let right = check_heights(&node.right, path)?;
+    path.pop();
+
+    let kind = if left > right + 1 || right > left + 1 {
+        ViolationKind::HeightsDiffer{ left, right }
+    } else if node.balance != 1 + cmp::max(left, right) {
+        ViolationKind::WrongHeight{ cached: node.balance, actual: 1 + cmp::max(left, right) }
+    } else {
+        return Ok(node.balance);
+    };
+    Err(InvariantViolation{ path: path.clone(), kind })
+}
+
+fn remove_min<T>(link: &mut Link<T>) -> Option<T> {
+    let removed = match *link {
+        Some(ref mut node) if node.left.is_some() => remove_min(&mut node.left),
+        Some(_) => {
+            let mut node = link.take().unwrap();
+            *link = node.right.take();
+            return Some(node.data);
+        },
+        None => return None
+    };
+
+    rebalance_link(link);
+    removed
+}
+
+fn height<T>(link: &Link<T>) -> u8 {
+    match *link {
+        Some(ref node) => node.balance,
+        None => 0
+    }
+}
+
+fn update<T>(node: &mut Node<T>) {
+    node.balance = 1 + cmp::max(height(&node.left), height(&node.right));
+    node.update_size();
+}
+
+fn rotate_left<T>(node: Box<Node<T>>) -> Box<Node<T>> {
+    let mut root = node.rotate_left();
+    update(root.left.as_mut().unwrap());
+    update(&mut root);
+    root
+}
+
+fn rotate_right<T>(node: Box<Node<T>>) -> Box<Node<T>> {
+    let mut root = node.rotate_right();
+    update(root.right.as_mut().unwrap());
+    update(&mut root);
+    root
+}
+
+// Restores the AVL property at `node`, whose subtrees are each balanced and
+// differ in height by at most two.
+fn rebalance<T>(mut node: Box<Node<T>>) -> Box<Node<T>> {
+    update(&mut node);
+    let left = height(&node.left);
+    let right = height(&node.right);
+
+    if left > right + 1 {
+        let child = node.left.take().unwrap();
+        node.left = Some(if height(&child.left) < height(&child.right) {
+            rotate_left(child)
+        } else {
+            child
+        });
+        rotate_right(node)
+    } else if right > left + 1 {
+        let child = node.right.take().unwrap();
+        node.right = Some(if height(&child.right) < height(&child.left) {
+            rotate_right(child)
+        } else {
+            child
+        });
+        rotate_left(node)
+    } else {
+        node
+    }
+}
+
+fn rebalance_link<T>(link: &mut Link<T>) {
+    if let Some(node) = link.take() {
+        *link = Some(rebalance(node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use avl::height;
+    use AvlTree;
+    use Node;
+    use {Side, ViolationKind};
+
+    #[test]
+    fn sorted_inserts_stay_shallow() {
+        let mut tree = AvlTree::new();
+        for i in 0..1023 {
+            tree.insert(i);
+        }
+        assert_eq!(Ok(()), tree.validate());
+        assert_eq!(10, height(&tree.root));
+        assert_eq!(1023, tree.len());
+        assert!(tree.iter().cloned().eq(0..1023));
+    }
+
+    #[test]
+    fn reverse_sorted_inserts_stay_shallow() {
+        let mut tree = AvlTree::new();
+        for i in (0..1000).rev() {
+            tree.insert(i);
+        }
+        assert_eq!(Ok(()), tree.validate());
+        assert!(height(&tree.root) <= 11);
+        assert!(tree.contains(&0));
+        assert!(tree.contains(&999));
+    }
+
+    #[test]
+    fn removes_stay_balanced() {
+        let mut tree = AvlTree::new();
+        for i in 0..1000 {
+            tree.insert(i);
+        }
+        for i in 0..900 {
+            assert_eq!(Some(i), tree.remove(&i));
+            assert_eq!(Ok(()), tree.validate());
+        }
+        assert_eq!(None, tree.remove(&0));
+        assert_eq!(100, tree.len());
+        assert!(height(&tree.root) <= 8);
+        assert!(tree.iter().cloned().eq(900..1000));
+    }
+
+    #[test]
+    fn from_sorted_is_balanced() {
+        let data: Vec<i32> = (0..100).collect();
+        let tree = AvlTree::from_sorted(&data);
+        assert_eq!(Ok(()), tree.validate());
+        assert_eq!(7, height(&tree.root));
+    }
+
+    #[test]
+    fn validate_checks_heights() {
+        let mut tree = AvlTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(Ok(()), tree.validate());
+        tree.root.as_mut().unwrap().left.as_mut().unwrap().balance = 3;
+        let violation = tree.validate().unwrap_err();
+        assert_eq!(vec![Side::Left], violation.path);
+        assert_eq!(ViolationKind::WrongHeight{ cached: 3, actual: 2 }, violation.kind);
+
+        // Hung together by hand, so nothing rebalances it.
+        let mut leaf = Node::leaf(3);
+        leaf.balance = 1;
+        let mut right = Node::branch(2, None, Some(Box::new(leaf)));
+        right.balance = 2;
+        let mut tree = AvlTree::new();
+        tree.insert(1);
+        tree.root.as_mut().unwrap().right = Some(Box::new(right));
+        tree.root.as_mut().unwrap().size = 3;
+        tree.len = 3;
+        let violation = tree.validate().unwrap_err();
+        assert!(violation.path.is_empty());
+        assert_eq!(ViolationKind::HeightsDiffer{ left: 0, right: 2 }, violation.kind);
+    }
+}
@@ -0,0 +1,84 @@
+use std::cmp::Ordering;
+
+use error::{InvariantViolation, Side};
+use Link;
+use Node;
+
+/// A strategy for keeping a `SearchTree` in shape as values are inserted and
+/// removed.
+///
+/// This trait is sealed; the strategies are `Unbalanced`, `Avl` and
+/// `RedBlack`.
+pub trait Balance : sealed::Sealed {}
+
+pub mod sealed {
+    use std::cmp::Ordering;
+
+    use error::{InvariantViolation, Side};
+    use Link;
+    use Node;
+
+    pub trait Sealed {
+        /// Inserts `value` below `link` where `order` places it, returning
+        /// its rank among the values there, or `None` if an equal value was
+        /// already there. In that case both values are handed to `on_equal`
+        /// instead.
+        fn insert<T, F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+            where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T);
+
+        /// Removes the value that `probe` matches from below `link`. `probe`
+        /// orders the value being looked for against a node, and is asked
+        /// about each node on the way down once, top to bottom. Only when
+        /// nothing matches may it be asked about them again.
+        fn remove<T, F>(link: &mut Link<T>, probe: &mut F) -> Option<T>
+            where F : FnMut(&Node<T>) -> Ordering;
+
+        /// Sets up the bookkeeping of a tree of `len` values that was
+        /// assembled bottom up rather than by insertion, and whose leaves all
+        /// lie on its last two levels.
+        fn build<T>(link: &mut Link<T>, len: usize);
+
+        /// Hangs `left` and `right` either side of `middle`, whose own
+        /// children are replaced, and rebalances the result. Every value in
+        /// `left` must be less than `middle`'s and every value in `right`
+        /// greater.
+        fn join<T>(left: Link<T>, middle: Box<Node<T>>, right: Link<T>) -> Box<Node<T>>;
+
+        /// Checks the bookkeeping below `link`, whose nodes are otherwise
+        /// known to be in order. `path` leads from the tree's root to `link`,
+        /// for reporting where a violation is.
+        fn validate<T>(link: &Link<T>, path: &mut Vec<Side>) -> Result<(), InvariantViolation>;
+    }
+}
+
+/// Never rebalances, so the tree's shape follows the order values were
+/// inserted in. This is the strategy behind `BinaryTree`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Unbalanced;
+
+impl Balance for Unbalanced {}
+
+impl sealed::Sealed for Unbalanced {
+    fn insert<T, F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+            where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+        Node::insert(link, value, order, on_equal)
+    }
+
+    fn remove<T, F>(link: &mut Link<T>, probe: &mut F) -> Option<T>
+            where F : FnMut(&Node<T>) -> Ordering {
+        Node::remove(link, probe)
+    }
+
+    fn build<T>(_: &mut Link<T>, _: usize) {}
+
+    fn join<T>(left: Link<T>, mut middle: Box<Node<T>>, right: Link<T>) -> Box<Node<T>> {
+        middle.left = left;
+        middle.right = right;
+        middle.update_size();
+        middle
+    }
+
+    fn validate<T>(_: &Link<T>, _: &mut Vec<Side>) -> Result<(), InvariantViolation> {
+        Ok(())
+    }
+}
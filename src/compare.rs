@@ -0,0 +1,94 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// An ordering on values of type `T`, which a `SearchTree` keeps its values
+/// sorted by.
+///
+/// Any closure taking two `&T` and returning an `Ordering` is a comparator,
+/// so a one-off order, such as comparing strings case-insensitively, needs no
+/// new type.
+pub trait Compare<T : ?Sized> {
+    /// Orders `a` against `b`.
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+impl <T : ?Sized, F : Fn(&T, &T) -> Ordering> Compare<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// The natural order of a type, given by its `Ord` implementation. Trees use
+/// this unless told otherwise.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Natural;
+
+impl <T : Ord + ?Sized> Compare<T> for Natural {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Reverses the order given by another comparator, the natural order unless
+/// told otherwise.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Reverse<C = Natural>(pub C);
+
+impl <T : ?Sized, C : Compare<T>> Compare<T> for Reverse<C> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(b, a)
+    }
+}
+
+/// Orders values by the natural order of a key taken from each of them.
+/// Made by `by_key`.
+#[derive(Clone,Copy)]
+pub struct ByKey<F> {
+    key: F,
+}
+
+/// Makes a comparator that orders values by the key `key` takes from them,
+/// such as one of their fields.
+pub fn by_key<T : ?Sized, K : Ord, F : Fn(&T) -> K>(key: F) -> ByKey<F> {
+    ByKey{ key }
+}
+
+impl <T : ?Sized, K : Ord, F : Fn(&T) -> K> Compare<T> for ByKey<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.key)(a).cmp(&(self.key)(b))
+    }
+}
+
+impl <F> fmt::Debug for ByKey<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ByKey")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cmp::Ordering;
+
+    use compare::{by_key, Compare, Natural, Reverse};
+
+    #[test]
+    fn natural_and_reverse() {
+        assert_eq!(Ordering::Less, Natural.compare(&1, &2));
+        assert_eq!(Ordering::Greater, Reverse(Natural).compare(&1, &2));
+        assert_eq!(Ordering::Less, Reverse(Reverse(Natural)).compare("a", "b"));
+    }
+
+    #[test]
+    fn by_key_compares_keys() {
+        let by_len = by_key(|s: &&str| s.len());
+        assert_eq!(Ordering::Less, by_len.compare(&"zz", &"aaa"));
+        assert_eq!(Ordering::Equal, by_len.compare(&"ab", &"cd"));
+    }
+
+    #[test]
+    fn closures_are_comparators() {
+        let case_insensitive = |a: &str, b: &str| a.to_lowercase().cmp(&b.to_lowercase());
+        assert_eq!(Ordering::Equal, case_insensitive.compare("Abc", "aBC"));
+    }
+}
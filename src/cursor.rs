@@ -0,0 +1,572 @@
+//! Cursors, which sit at a position in a `SearchTree` and move back and forth
+//! from there.
+//!
+//! A cursor points at one of the tree's values, or at a "ghost" position
+//! past the last value and before the first, so moving off either end wraps
+//! round through the ghost. Like the iterators, a cursor keeps the nodes on
+//! the way down to its value, so reading the value takes constant time, and
+//! so does moving, on average over a walk. A peek may look down or back up
+//! the tree, taking time proportional to its height.
+
+use std::cmp::Ordering;
+use std::ptr::{self, NonNull};
+
+use node;
+use Balance;
+use Compare;
+use Link;
+use Node;
+use SearchTree;
+
+// A way back to a node of the tree a cursor is over.
+pub(crate) trait NodeRef<'a, T : 'a> : Copy {
+    fn from_node(node: &'a Node<T>) -> Self;
+    fn node(self, root: &'a Link<T>) -> &'a Node<T>;
+}
+
+impl <'a, T : 'a> NodeRef<'a, T> for &'a Node<T> {
+    fn from_node(node: &'a Node<T>) -> &'a Node<T> {
+        node
+    }
+
+    fn node(self, _: &'a Link<T>) -> &'a Node<T> {
+        self
+    }
+}
+
+// A `CursorMut` can't hold references into the tree it changes, so it keeps
+// pointers instead, made from shared references and only ever read through.
+impl <'a, T : 'a> NodeRef<'a, T> for NonNull<Node<T>> {
+    fn from_node(node: &'a Node<T>) -> NonNull<Node<T>> {
+        NonNull::from(node)
+    }
+
+    fn node(self, _: &'a Link<T>) -> &'a Node<T> {
+        // Safe: a `CursorMut` only changes its tree through `insert` and
+        // `remove_at`, and finds its position again straight after, so
+        // nothing has been written to or moved since these pointers were
+        // made from the tree under `root`, which stays borrowed as long.
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+// Where a cursor points: at the value at the end of `path`, or at the ghost
+// when `path` is empty.
+#[derive(Clone)]
+pub(crate) struct Position<P> {
+    // The nodes from the root down to the current one.
+    path: Vec<P>,
+    // The rank of the current value, or the tree's length at the ghost.
+    index: usize,
+}
+
+impl <P> Position<P> {
+    // The position of the least value that `probe` doesn't put before the
+    // value being looked for. `probe` orders that value against a node's.
+    pub(crate) fn lower_bound<'a, T : 'a, F>(root: &'a Link<T>, probe: F) -> Position<P>
+            where P : NodeRef<'a, T>, F : Fn(&T) -> Ordering {
+        let mut path = Vec::new();
+        let (mut found, mut index) = (0, 0);
+        let mut link = root;
+        while let Some(ref node) = *link {
+            path.push(P::from_node(node));
+            if probe(&node.data) == Ordering::Greater {
+                index += node::size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                found = path.len();
+                link = &node.left;
+            }
+        }
+        // Anything below the lower bound was only passed on the way down.
+        path.truncate(found);
+        Position{ path, index }
+    }
+
+    // The position of the value ranked `index`, or the ghost if there is
+    // none.
+    pub(crate) fn at<'a, T : 'a>(root: &'a Link<T>, index: usize) -> Position<P>
+            where P : NodeRef<'a, T> {
+        let mut path = Vec::new();
+        let (mut k, mut link) = (index, root);
+        while let Some(ref node) = *link {
+            path.push(P::from_node(node));
+            let left = node::size(&node.left);
+            match k.cmp(&left) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Position{ path, index },
+                Ordering::Greater => {
+                    k -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        Position{ path: Vec::new(), index: node::size(root) }
+    }
+
+    fn current<'a, T : 'a>(&self, root: &'a Link<T>) -> Option<&'a T>
+            where P : NodeRef<'a, T> {
+        self.path.last().map(|node| &node.node(root).data)
+    }
+
+    fn push_left_spine<'a, T : 'a>(&mut self, mut link: &'a Link<T>)
+            where P : NodeRef<'a, T> {
+        while let Some(ref node) = *link {
+            self.path.push(P::from_node(node));
+            link = &node.left;
+        }
+    }
+
+    fn push_right_spine<'a, T : 'a>(&mut self, mut link: &'a Link<T>)
+            where P : NodeRef<'a, T> {
+        while let Some(ref node) = *link {
+            self.path.push(P::from_node(node));
+            link = &node.right;
+        }
+    }
+
+    fn move_next<'a, T : 'a>(&mut self, root: &'a Link<T>)
+            where P : NodeRef<'a, T> {
+        let node = match self.path.last() {
+            Some(node) => node.node(root),
+            None => {
+                self.push_left_spine(root);
+                self.index = 0;
+                return;
+            }
+        };
+
+        if node.right.is_some() {
+            self.push_left_spine(&node.right);
+        } else {
+            // Back up to the first node this one is to the left of.
+            let mut child = node;
+            self.path.pop();
+            while let Some(parent) = self.path.last() {
+                let parent = parent.node(root);
+                if is_child(&parent.left, child) {
+                    break;
+                }
+                child = parent;
+                self.path.pop();
+            }
+        }
+        self.index += 1;
+    }
+
+    fn move_prev<'a, T : 'a>(&mut self, root: &'a Link<T>)
+            where P : NodeRef<'a, T> {
+        let node = match self.path.last() {
+            Some(node) => node.node(root),
+            None => {
+                self.push_right_spine(root);
+                self.index = node::size(root).saturating_sub(1);
+                return;
+            }
+        };
+
+        if node.left.is_some() {
+            self.push_right_spine(&node.left);
+        } else {
+            let mut child = node;
+            self.path.pop();
+            while let Some(parent) = self.path.last() {
+                let parent = parent.node(root);
+                if is_child(&parent.right, child) {
+                    break;
+                }
+                child = parent;
+                self.path.pop();
+            }
+        }
+        self.index = if self.path.is_empty() { node::size(root) } else { self.index - 1 };
+    }
+
+    fn peek_next<'a, T : 'a>(&self, root: &'a Link<T>) -> Option<&'a T>
+            where P : NodeRef<'a, T> {
+        let node = match self.path.last() {
+            Some(node) => node.node(root),
+            None => return leftmost(root)
+        };
+        if node.right.is_some() {
+            return leftmost(&node.right);
+        }
+        let mut child = node;
+        for parent in self.path.iter().rev().skip(1) {
+            let parent = parent.node(root);
+            if is_child(&parent.left, child) {
+                return Some(&parent.data);
+            }
+            child = parent;
+        }
+        None
+    }
+
+    fn peek_prev<'a, T : 'a>(&self, root: &'a Link<T>) -> Option<&'a T>
+            where P : NodeRef<'a, T> {
+        let node = match self.path.last() {
+            Some(node) => node.node(root),
+            None => return rightmost(root)
+        };
+        if node.left.is_some() {
+            return rightmost(&node.left);
+        }
+        let mut child = node;
+        for parent in self.path.iter().rev().skip(1) {
+            let parent = parent.node(root);
+            if is_child(&parent.right, child) {
+                return Some(&parent.data);
+            }
+            child = parent;
+        }
+        None
+    }
+}
+
+fn is_child<T>(link: &Link<T>, node: &Node<T>) -> bool {
+    link.as_ref().is_some_and(|child| ptr::eq(&**child, node))
+}
+
+fn leftmost<T>(mut link: &Link<T>) -> Option<&T> {
+    let mut found = None;
+    while let Some(ref node) = *link {
+        found = Some(&node.data);
+        link = &node.left;
+    }
+    found
+}
+
+fn rightmost<T>(mut link: &Link<T>) -> Option<&T> {
+    let mut found = None;
+    while let Some(ref node) = *link {
+        found = Some(&node.data);
+        link = &node.right;
+    }
+    found
+}
+
+/// A cursor over a `SearchTree`. Made by `SearchTree::lower_bound_cursor`.
+pub struct Cursor<'a, T : 'a, B : Balance + 'a, C : 'a> {
+    tree: &'a SearchTree<T, B, C>,
+    position: Position<&'a Node<T>>,
+}
+
+impl <'a, T, B : Balance, C> Cursor<'a, T, B, C> {
+    pub(crate) fn new(tree: &'a SearchTree<T, B, C>, position: Position<&'a Node<T>>) -> Cursor<'a, T, B, C> {
+        Cursor{ tree, position }
+    }
+
+    /// Returns the value the cursor points at, or `None` at the ghost.
+    pub fn current(&self) -> Option<&'a T> {
+        self.position.current(&self.tree.root)
+    }
+
+    /// Returns the rank of the value the cursor points at, or `None` at the
+    /// ghost.
+    pub fn index(&self) -> Option<usize> {
+        if self.position.path.is_empty() { None } else { Some(self.position.index) }
+    }
+
+    /// Moves to the next value, from the ghost to the first value, and from
+    /// the last value to the ghost.
+    pub fn move_next(&mut self) {
+        self.position.move_next(&self.tree.root);
+    }
+
+    /// Moves to the previous value, from the ghost to the last value, and
+    /// from the first value to the ghost.
+    pub fn move_prev(&mut self) {
+        self.position.move_prev(&self.tree.root);
+    }
+
+    /// Returns the value `move_next` would move to, or `None` if it would
+    /// move to the ghost.
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.position.peek_next(&self.tree.root)
+    }
+
+    /// Returns the value `move_prev` would move to, or `None` if it would
+    /// move to the ghost.
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        self.position.peek_prev(&self.tree.root)
+    }
+}
+
+impl <'a, T, B : Balance, C> Clone for Cursor<'a, T, B, C> {
+    fn clone(&self) -> Cursor<'a, T, B, C> {
+        Cursor{ tree: self.tree, position: self.position.clone() }
+    }
+}
+
+/// A cursor over a `SearchTree` that can also insert and remove values where
+/// it points. Made by `SearchTree::lower_bound_cursor_mut`.
+pub struct CursorMut<'a, T : 'a, B : Balance + 'a, C : 'a> {
+    tree: &'a mut SearchTree<T, B, C>,
+    position: Position<NonNull<Node<T>>>,
+}
+
+impl <'a, T, B : Balance, C> CursorMut<'a, T, B, C> {
+    pub(crate) fn new(tree: &'a mut SearchTree<T, B, C>, position: Position<NonNull<Node<T>>>) -> CursorMut<'a, T, B, C> {
+        CursorMut{ tree, position }
+    }
+
+    /// Returns the value the cursor points at, or `None` at the ghost.
+    pub fn current(&self) -> Option<&T> {
+        self.position.current(&self.tree.root)
+    }
+
+    /// Returns the rank of the value the cursor points at, or `None` at the
+    /// ghost.
+    pub fn index(&self) -> Option<usize> {
+        if self.position.path.is_empty() { None } else { Some(self.position.index) }
+    }
+
+    /// Moves to the next value, from the ghost to the first value, and from
+    /// the last value to the ghost.
+    pub fn move_next(&mut self) {
+        self.position.move_next(&self.tree.root);
+    }
+
+    /// Moves to the previous value, from the ghost to the last value, and
+    /// from the first value to the ghost.
+    pub fn move_prev(&mut self) {
+        self.position.move_prev(&self.tree.root);
+    }
+
+    /// Returns the value `move_next` would move to, or `None` if it would
+    /// move to the ghost.
+    pub fn peek_next(&self) -> Option<&T> {
+        self.position.peek_next(&self.tree.root)
+    }
+
+    /// Returns the value `move_prev` would move to, or `None` if it would
+    /// move to the ghost.
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.position.peek_prev(&self.tree.root)
+    }
+
+    /// Returns a read-only cursor pointing where this one does.
+    pub fn as_cursor(&self) -> Cursor<'_, T, B, C> {
+        let root = &self.tree.root;
+        let path = self.position.path.iter().map(|node| node.node(root)).collect();
+        Cursor::new(self.tree, Position{ path, index: self.position.index })
+    }
+
+    /// Removes and returns the value the cursor points at, moving on to the
+    /// next one. Does nothing at the ghost.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.position.path.is_empty() {
+            return None;
+        }
+        let removed = self.tree.remove_at(self.position.index);
+        self.seek(self.position.index);
+        removed
+    }
+
+    // Finds the value ranked `index` again after the tree has changed.
+    fn seek(&mut self, index: usize) {
+        self.position = Position::at(&self.tree.root, index);
+    }
+}
+
+impl <'a, T, B : Balance, C : Compare<T>> CursorMut<'a, T, B, C> {
+    /// Inserts `value` just before the value the cursor points at, which it
+    /// goes on pointing at. At the ghost, `value` becomes the last value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` doesn't lie strictly between the values either side
+    /// of where it goes.
+    pub fn insert_before(&mut self, value: T) {
+        let fits = self.less(self.peek_prev(), Some(&value)) && self.less(Some(&value), self.current());
+        assert!(fits, "value out of order in CursorMut::insert_before");
+        self.tree.insert(value);
+        self.seek(self.position.index + 1);
+    }
+
+    /// Inserts `value` just after the value the cursor points at, which it
+    /// goes on pointing at. At the ghost, `value` becomes the first value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` doesn't lie strictly between the values either side
+    /// of where it goes.
+    pub fn insert_after(&mut self, value: T) {
+        let fits = self.less(self.current(), Some(&value)) && self.less(Some(&value), self.peek_next());
+        assert!(fits, "value out of order in CursorMut::insert_after");
+        let ghost = self.position.path.is_empty();
+        self.tree.insert(value);
+        self.seek(if ghost { self.tree.len() } else { self.position.index });
+    }
+
+    // Whether `a` is less than `b`. A missing value is the ghost, which
+    // anything is in order with.
+    fn less(&self, a: Option<&T>, b: Option<&T>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => self.tree.compare.compare(a, b) == Ordering::Less,
+            _ => true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use {AvlTree, Balance, BinaryTree, SearchTree};
+
+    fn tens() -> AvlTree<u32> {
+        (1..6).map(|i| i * 10).collect()
+    }
+
+    #[test]
+    fn lower_bound_cursor_finds_the_first_value_not_less() {
+        let tree = tens();
+        assert_eq!(Some(&30), tree.lower_bound_cursor(&30).current());
+        assert_eq!(Some(&30), tree.lower_bound_cursor(&25).current());
+        assert_eq!(Some(&10), tree.lower_bound_cursor(&0).current());
+        assert_eq!(None, tree.lower_bound_cursor(&51).current());
+        assert_eq!(None, tree.lower_bound_cursor(&51).index());
+        assert_eq!(Some(2), tree.lower_bound_cursor(&21).index());
+    }
+
+    #[test]
+    fn moves_wrap_round_through_the_ghost() {
+        let tree = tens();
+        let mut cursor = tree.lower_bound_cursor(&40);
+        assert_eq!(Some(&30), cursor.peek_prev());
+        assert_eq!(Some(&50), cursor.peek_next());
+        cursor.move_next();
+        assert_eq!(Some(&50), cursor.current());
+        assert_eq!(None, cursor.peek_next());
+        cursor.move_next();
+        assert_eq!(None, cursor.current());
+        assert_eq!(Some(&10), cursor.peek_next());
+        assert_eq!(Some(&50), cursor.peek_prev());
+        cursor.move_next();
+        assert_eq!(Some(&10), cursor.current());
+        assert_eq!(None, cursor.peek_prev());
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(Some(&50), cursor.current());
+    }
+
+    #[test]
+    fn cursor_on_an_empty_tree() {
+        let tree: BinaryTree<u32> = BinaryTree::new();
+        let mut cursor = tree.lower_bound_cursor(&1);
+        assert_eq!(None, cursor.current());
+        cursor.move_next();
+        assert_eq!(None, cursor.current());
+        assert_eq!(None, cursor.peek_prev());
+    }
+
+    #[test]
+    fn merge_join() {
+        let left = tens();
+        let right: AvlTree<u32> = (0..20).map(|i| i * 3).collect();
+        let mut a = left.lower_bound_cursor(&0);
+        let mut b = right.lower_bound_cursor(&0);
+        let mut matched = Vec::new();
+        while let (Some(x), Some(y)) = (a.current(), b.current()) {
+            if x < y {
+                a.move_next();
+            } else if y < x {
+                b.move_next();
+            } else {
+                matched.push(*x);
+                a.move_next();
+                b.move_next();
+            }
+        }
+        assert_eq!(vec![30], matched);
+    }
+
+    #[test]
+    fn insert_around_the_cursor() {
+        let mut tree = tens();
+        {
+            let mut cursor = tree.lower_bound_cursor_mut(&30);
+            cursor.insert_before(25);
+            cursor.insert_after(35);
+            assert_eq!(Some(&30), cursor.current());
+            assert_eq!(Some(&25), cursor.peek_prev());
+            assert_eq!(Some(&35), cursor.peek_next());
+            cursor.move_next();
+            cursor.move_next();
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(None, cursor.current());
+            cursor.insert_after(5);
+            cursor.insert_before(55);
+            assert_eq!(None, cursor.current());
+            assert_eq!(Some(&5), cursor.peek_next());
+            assert_eq!(Some(&55), cursor.peek_prev());
+        }
+        assert_eq!(Ok(()), tree.validate());
+        assert!(tree.iter().cloned().eq(vec![5, 10, 20, 25, 30, 35, 40, 50, 55]));
+    }
+
+    #[test]
+    #[should_panic(expected = "value out of order in CursorMut::insert_before")]
+    fn insert_before_panics_when_out_of_order() {
+        let mut tree = tens();
+        tree.lower_bound_cursor_mut(&30).insert_before(30);
+    }
+
+    #[test]
+    #[should_panic(expected = "value out of order in CursorMut::insert_after")]
+    fn insert_after_panics_when_out_of_order() {
+        let mut tree = tens();
+        tree.lower_bound_cursor_mut(&30).insert_after(45);
+    }
+
+    fn walk_both_ways<B : Balance>() {
+        let tree: SearchTree<u32, B> = (0..200).map(|i| i * 7 % 200).collect();
+        let mut cursor = tree.lower_bound_cursor(&0);
+        for i in 0..200 {
+            assert_eq!(Some(&i), cursor.current());
+            assert_eq!(Some(i as usize), cursor.index());
+            assert_eq!(i.checked_sub(1).as_ref(), cursor.peek_prev());
+            assert_eq!(Some(i + 1).filter(|&next| next < 200).as_ref(), cursor.peek_next());
+            cursor.move_next();
+        }
+        assert_eq!(None, cursor.current());
+        for i in (0..200).rev() {
+            cursor.move_prev();
+            assert_eq!(Some(&i), cursor.current());
+            assert_eq!(Some(i as usize), cursor.index());
+        }
+        cursor.move_prev();
+        assert_eq!(None, cursor.index());
+    }
+
+    test_each_balance!(cursors_walk_both_ways, walk_both_ways);
+
+    fn remove_every_other<B : Balance>() {
+        let mut tree: SearchTree<u32, B> = (0..100).collect();
+        {
+            let mut cursor = tree.lower_bound_cursor_mut(&10);
+            while cursor.current().is_some_and(|&i| i < 90) {
+                if cursor.current().is_some_and(|i| i % 2 == 0) {
+                    assert!(cursor.remove_current().is_some());
+                } else {
+                    cursor.move_next();
+                }
+            }
+            assert_eq!(Some(&90), cursor.current());
+            assert_eq!(Some(&89), cursor.peek_prev());
+            cursor.move_prev();
+            cursor.move_prev();
+            assert_eq!(Some(&87), cursor.current());
+        }
+        assert_eq!(Ok(()), tree.validate());
+        assert!(tree.iter().cloned().eq((0..10).chain((10..90).filter(|i| i % 2 == 1)).chain(90..100)));
+
+        let mut cursor = tree.lower_bound_cursor_mut(&99);
+        assert_eq!(Some(99), cursor.remove_current());
+        assert_eq!(None, cursor.current());
+        assert_eq!(None, cursor.remove_current());
+    }
+
+    test_each_balance!(remove_current_moves_on, remove_every_other);
+}
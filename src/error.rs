@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why `try_from_sorted` refused to build a tree.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum FromSortedError {
+    /// The value at `index` is less than the one before it.
+    NotSorted { index: usize },
+    /// The value at `index` is equal to the one before it.
+    Duplicate { index: usize },
+    /// There were no values at all.
+    Empty,
+}
+
+impl fmt::Display for FromSortedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FromSortedError::NotSorted{ index } =>
+                write!(f, "value at index {} is less than the one before it", index),
+            FromSortedError::Duplicate{ index } =>
+                write!(f, "value at index {} is equal to the one before it", index),
+            FromSortedError::Empty => f.write_str("no values to build a tree from")
+        }
+    }
+}
+
+impl Error for FromSortedError {}
+
+/// A broken invariant found by `SearchTree::validate`, and where.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct InvariantViolation {
+    /// The way down from the root to the offending node, empty for the root
+    /// or for the tree as a whole.
+    pub path: Vec<Side>,
+    /// What is wrong there.
+    pub kind: ViolationKind,
+}
+
+/// A step down from a node to one of its children.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// The invariants `SearchTree::validate` checks.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ViolationKind {
+    /// The node's value doesn't lie strictly between the values of the
+    /// ancestors it is meant to lie between.
+    OutOfOrder,
+    /// The node's cached subtree size isn't one more than its children's.
+    WrongSize { cached: usize, actual: usize },
+    /// The tree's length isn't the number of nodes in it.
+    WrongLength { len: usize, nodes: usize },
+    /// The node's cached AVL height isn't one more than its taller child's.
+    WrongHeight { cached: u8, actual: u8 },
+    /// The heights of the node's subtrees differ by more than one.
+    HeightsDiffer { left: u8, right: u8 },
+    /// The node is coloured neither red nor black.
+    BadColour(u8),
+    /// The root of a red-black tree is red.
+    RedRoot,
+    /// The node is red, and so is one of its children.
+    RedChild,
+    /// The node's subtrees have different numbers of black nodes on their
+    /// paths down.
+    BlackHeightsDiffer { left: usize, right: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ViolationKind::OutOfOrder => f.write_str("value out of order")?,
+            ViolationKind::WrongSize{ cached, actual } =>
+                write!(f, "cached size {} should be {}", cached, actual)?,
+            ViolationKind::WrongLength{ len, nodes } =>
+                write!(f, "length {} but {} nodes", len, nodes)?,
+            ViolationKind::WrongHeight{ cached, actual } =>
+                write!(f, "cached height {} should be {}", cached, actual)?,
+            ViolationKind::HeightsDiffer{ left, right } =>
+                write!(f, "subtree heights {} and {} differ by more than one", left, right)?,
+            ViolationKind::BadColour(colour) => write!(f, "unknown colour {}", colour)?,
+            ViolationKind::RedRoot => f.write_str("red root")?,
+            ViolationKind::RedChild => f.write_str("red node with a red child")?,
+            ViolationKind::BlackHeightsDiffer{ left, right } =>
+                write!(f, "subtrees with {} and {} black nodes on each path", left, right)?
+        }
+
+        f.write_str(" at root")?;
+        for side in &self.path {
+            f.write_str(match *side {
+                Side::Left => ".left",
+                Side::Right => ".right"
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for InvariantViolation {}
+
+/// Why `try_left`, `try_right` or `try_branch` refused to put a value above
+/// subtrees.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum BranchError {
+    /// The left subtree holds a value that isn't less than the new root.
+    LeftNotLess,
+    /// The right subtree holds a value that isn't greater than the new root.
+    RightNotGreater,
+}
+
+impl fmt::Display for BranchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BranchError::LeftNotLess => "left subtree holds a value not less than the root",
+            BranchError::RightNotGreater => "right subtree holds a value not greater than the root"
+        })
+    }
+}
+
+impl Error for BranchError {}
@@ -0,0 +1,596 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+
+use cursor::{CursorMut, Position};
+use Balance;
+use Compare;
+use Link;
+use Node;
+use node;
+use SearchTree;
+
+/// In-order iterator over the values of a `SearchTree`, from smallest to
+/// largest. It can also be walked from the largest value backwards.
+pub struct Iter<'a, T : 'a> {
+    front: Vec<&'a Node<T>>,
+    back: Vec<&'a Node<T>>,
+    // The two stacks are walked independently, so this count is what stops
+    // them from passing each other.
+    remaining: usize,
+}
+
+impl <'a, T> Iter<'a, T> {
+    pub(crate) fn new<B : Balance, C>(tree: &'a SearchTree<T, B, C>) -> Iter<'a, T> {
+        let mut iter = Iter{ front: Vec::new(), back: Vec::new(), remaining: tree.len };
+        iter.push_left_spine(&tree.root);
+        iter.push_right_spine(&tree.root);
+        iter
+    }
+
+    fn push_left_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(ref node) = *link {
+            self.front.push(node);
+            link = &node.left;
+        }
+    }
+
+    fn push_right_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(ref node) = *link {
+            self.back.push(node);
+            link = &node.right;
+        }
+    }
+}
+
+impl <'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        self.push_left_spine(&node.right);
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        self.push_right_spine(&node.left);
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+}
+
+impl <'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// In-order iterator over the values of a `SearchTree` that fall within a
+/// range. Subtrees outside the range are never visited.
+pub struct Range<'a, T : 'a> {
+    front: Vec<&'a Node<T>>,
+    back: Vec<&'a Node<T>>,
+    // The size of the range isn't known up front, so the ends stop when one
+    // of them yields the node the other would have yielded next.
+    done: bool,
+}
+
+impl <'a, T> Range<'a, T> {
+    pub(crate) fn new<B, C, Q, R>(tree: &'a SearchTree<T, B, C>, range: R) -> Range<'a, T>
+            where B : Balance, C : Compare<Q>, T : Borrow<Q>, Q : ?Sized, R : RangeBounds<Q> {
+        let compare = &tree.compare;
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if compare.compare(s, e) == Ordering::Equal =>
+                panic!("range start and end are equal and excluded in SearchTree"),
+            (Bound::Included(s), Bound::Included(e)) |
+            (Bound::Included(s), Bound::Excluded(e)) |
+            (Bound::Excluded(s), Bound::Included(e)) |
+            (Bound::Excluded(s), Bound::Excluded(e)) if compare.compare(s, e) == Ordering::Greater =>
+                panic!("range start is greater than range end in SearchTree"),
+            _ => {}
+        }
+
+        let mut front = Vec::new();
+        let mut link = &tree.root;
+        while let Some(ref node) = *link {
+            if above_start(compare, node.data.borrow(), range.start_bound()) {
+                front.push(&**node);
+                link = &node.left;
+            } else {
+                link = &node.right;
+            }
+        }
+
+        let mut back = Vec::new();
+        let mut link = &tree.root;
+        while let Some(ref node) = *link {
+            if below_end(compare, node.data.borrow(), range.end_bound()) {
+                back.push(&**node);
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+
+        let done = match (front.last(), back.last()) {
+            (Some(first), Some(last)) => compare.compare(first.data.borrow(), last.data.borrow()) == Ordering::Greater,
+            _ => true
+        };
+
+        Range{ front, back, done }
+    }
+
+    fn push_left_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(ref node) = *link {
+            self.front.push(node);
+            link = &node.left;
+        }
+    }
+
+    fn push_right_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(ref node) = *link {
+            self.back.push(node);
+            link = &node.right;
+        }
+    }
+}
+
+fn above_start<C : Compare<Q>, Q : ?Sized>(compare: &C, value: &Q, start: Bound<&Q>) -> bool {
+    match start {
+        Bound::Included(start) => compare.compare(value, start) != Ordering::Less,
+        Bound::Excluded(start) => compare.compare(value, start) == Ordering::Greater,
+        Bound::Unbounded => true
+    }
+}
+
+fn below_end<C : Compare<Q>, Q : ?Sized>(compare: &C, value: &Q, end: Bound<&Q>) -> bool {
+    match end {
+        Bound::Included(end) => compare.compare(value, end) != Ordering::Greater,
+        Bound::Excluded(end) => compare.compare(value, end) == Ordering::Less,
+        Bound::Unbounded => true
+    }
+}
+
+impl <'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        if self.back.last().is_none_or(|last| ptr::eq(*last, node)) {
+            self.done = true;
+        }
+        self.push_left_spine(&node.right);
+        Some(&node.data)
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Range<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        if self.front.last().is_none_or(|first| ptr::eq(*first, node)) {
+            self.done = true;
+        }
+        self.push_right_spine(&node.left);
+        Some(&node.data)
+    }
+}
+
+/// Pre-order iterator over the values of a `SearchTree`: each value is
+/// yielded before the values in its left and then right subtrees.
+pub struct Preorder<'a, T : 'a> {
+    stack: Vec<&'a Node<T>>,
+}
+
+impl <'a, T> Preorder<'a, T> {
+    pub(crate) fn new<B : Balance, C>(tree: &'a SearchTree<T, B, C>) -> Preorder<'a, T> {
+        Preorder{ stack: tree.root.iter().map(|node| &**node).collect() }
+    }
+}
+
+impl <'a, T> Iterator for Preorder<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        if let Some(ref right) = node.right {
+            self.stack.push(right);
+        }
+        if let Some(ref left) = node.left {
+            self.stack.push(left);
+        }
+        Some(&node.data)
+    }
+}
+
+/// Post-order iterator over the values of a `SearchTree`: each value is
+/// yielded after the values in its left and then right subtrees.
+pub struct Postorder<'a, T : 'a> {
+    // The flag records whether the node's children have already been pushed.
+    stack: Vec<(&'a Node<T>, bool)>,
+}
+
+impl <'a, T> Postorder<'a, T> {
+    pub(crate) fn new<B : Balance, C>(tree: &'a SearchTree<T, B, C>) -> Postorder<'a, T> {
+        Postorder{ stack: tree.root.iter().map(|node| (&**node, false)).collect() }
+    }
+}
+
+impl <'a, T> Iterator for Postorder<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some(&node.data);
+            }
+
+            self.stack.push((node, true));
+            if let Some(ref right) = node.right {
+                self.stack.push((right, false));
+            }
+            if let Some(ref left) = node.left {
+                self.stack.push((left, false));
+            }
+        }
+    }
+}
+
+/// Level-order (breadth first) iterator over the values of a `SearchTree`,
+/// from the root downwards and left to right within each level.
+pub struct LevelOrder<'a, T : 'a> {
+    queue: VecDeque<&'a Node<T>>,
+}
+
+impl <'a, T> LevelOrder<'a, T> {
+    pub(crate) fn new<B : Balance, C>(tree: &'a SearchTree<T, B, C>) -> LevelOrder<'a, T> {
+        LevelOrder{ queue: tree.root.iter().map(|node| &**node).collect() }
+    }
+}
+
+impl <'a, T> Iterator for LevelOrder<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.queue.pop_front()?;
+        if let Some(ref left) = node.left {
+            self.queue.push_back(left);
+        }
+        if let Some(ref right) = node.right {
+            self.queue.push_back(right);
+        }
+        Some(&node.data)
+    }
+}
+
+/// Owning in-order iterator over the values of a `SearchTree`.
+pub struct IntoIter<T> {
+    stack: Vec<Box<Node<T>>>,
+    remaining: usize,
+}
+
+impl <T> IntoIter<T> {
+    pub(crate) fn new<B : Balance, C>(mut tree: SearchTree<T, B, C>) -> IntoIter<T> {
+        IntoIter::from_root(tree.root.take(), tree.len)
+    }
+
+    pub(crate) fn from_root(root: Link<T>, len: usize) -> IntoIter<T> {
+        let mut iter = IntoIter{ stack: Vec::new(), remaining: len };
+        iter.push_left_spine(root);
+        iter
+    }
+
+    fn push_left_spine(&mut self, mut link: Link<T>) {
+        while let Some(mut node) = link {
+            link = node.left.take();
+            self.stack.push(node);
+        }
+    }
+}
+
+impl <T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let mut node = self.stack.pop()?;
+        self.push_left_spine(node.right.take());
+        self.remaining -= 1;
+        Some(node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl <T> ExactSizeIterator for IntoIter<T> {}
+
+impl <T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        for node in self.stack.drain(..) {
+            node::tear_down(Some(node));
+        }
+    }
+}
+
+/// Iterator that moves the values a predicate picks out of a `SearchTree`,
+/// in order. Made by `SearchTree::extract_if`.
+///
+/// Each value is removed from the tree as it is yielded, so values the
+/// iterator never gets to are left where they are.
+pub struct ExtractIf<'a, T : 'a, B : Balance + 'a, C : 'a, F> {
+    cursor: CursorMut<'a, T, B, C>,
+    // The values the cursor has still to look at.
+    remaining: usize,
+    pred: F,
+}
+
+impl <'a, T, B : Balance, C, F : FnMut(&T) -> bool> ExtractIf<'a, T, B, C, F> {
+    pub(crate) fn new(tree: &'a mut SearchTree<T, B, C>, pred: F) -> ExtractIf<'a, T, B, C, F> {
+        let remaining = tree.len;
+        let position = Position::at(&tree.root, 0);
+        ExtractIf{ cursor: CursorMut::new(tree, position), remaining, pred }
+    }
+}
+
+impl <'a, T, B : Balance, C, F : FnMut(&T) -> bool> Iterator for ExtractIf<'a, T, B, C, F> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // A value `pred` panics on is still in the tree, as nothing has
+        // been taken out yet.
+        while let Some(value) = self.cursor.current() {
+            self.remaining -= 1;
+            if (self.pred)(value) {
+                return self.cursor.remove_current();
+            }
+            self.cursor.move_next();
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+impl <'a, T, B : Balance, C> IntoIterator for &'a SearchTree<T, B, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <T, B : Balance, C> IntoIterator for SearchTree<T, B, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::ops::Bound;
+    use std::panic;
+
+    use {AvlTree, BinaryTree, RedBlackTree};
+
+    fn seven() -> BinaryTree<i32> {
+        BinaryTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7])
+    }
+
+    #[test]
+    fn iter_is_sorted() {
+        let bt = BinaryTree::from(&mut [5, 3, 9, 1, 7]);
+        assert_eq!(vec![&1, &3, &5, &7, &9], bt.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn iter_empty() {
+        let bt: BinaryTree<i32> = BinaryTree::new();
+        assert_eq!(None, bt.iter().next());
+        assert_eq!(None, bt.preorder().next());
+        assert_eq!(None, bt.postorder().next());
+        assert_eq!(None, bt.level_order().next());
+        assert_eq!(None, bt.into_iter().next());
+    }
+
+    #[test]
+    fn iter_after_inserts() {
+        let mut bt = BinaryTree::new();
+        for i in &[4, 8, 2, 6, 1] {
+            bt.insert(*i);
+        }
+        assert_eq!(vec![1, 2, 4, 6, 8], bt.iter().cloned().collect::<Vec<_>>());
+        assert_eq!((5, Some(5)), bt.iter().size_hint());
+    }
+
+    #[test]
+    fn iter_rev() {
+        let bt = BinaryTree::from(&mut [5, 3, 9, 1, 7]);
+        assert_eq!(vec![9, 7, 5, 3, 1], bt.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn iter_from_both_ends_meets_in_the_middle() {
+        let bt = seven();
+        let mut iter = bt.iter();
+        assert_eq!(7, iter.len());
+        assert_eq!(Some(&1), iter.next());
+        assert_eq!(Some(&7), iter.next_back());
+        assert_eq!(Some(&6), iter.next_back());
+        assert_eq!(Some(&2), iter.next());
+        assert_eq!(3, iter.len());
+        assert_eq!(Some(&3), iter.next());
+        assert_eq!(Some(&5), iter.next_back());
+        assert_eq!(Some(&4), iter.next());
+        assert_eq!(None, iter.next_back());
+        assert_eq!(None, iter.next());
+        assert_eq!(0, iter.len());
+    }
+
+    #[test]
+    fn into_iter_len() {
+        let mut iter = seven().into_iter();
+        iter.next();
+        assert_eq!(6, iter.len());
+    }
+
+    #[test]
+    fn range_bounds() {
+        let bt = BinaryTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(vec![3, 4, 5], bt.range(3..6).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![3, 4, 5, 6], bt.range(3..=6).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![1, 2], bt.range(..3).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![8, 9], bt.range(8..).cloned().collect::<Vec<_>>());
+        assert_eq!(9, bt.range(..).count());
+        assert_eq!(vec![4, 5], bt.range((Bound::Excluded(3), Bound::Excluded(6))).cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn range_between_values() {
+        let bt = BinaryTree::from(&mut [10, 20, 30, 40, 50]);
+        assert_eq!(vec![20, 30], bt.range(15..35).cloned().collect::<Vec<_>>());
+        assert_eq!(0, bt.range(31..39).count());
+        assert_eq!(0, bt.range(60..).count());
+        assert_eq!(0, bt.range(..10).count());
+        assert_eq!(0, bt.range(20..20).count());
+    }
+
+    #[test]
+    fn range_rev_and_mixed() {
+        let bt = BinaryTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(vec![7, 6, 5, 4, 3], bt.range(3..8).rev().cloned().collect::<Vec<_>>());
+
+        let mut range = bt.range(2..=5);
+        assert_eq!(Some(&2), range.next());
+        assert_eq!(Some(&5), range.next_back());
+        assert_eq!(Some(&3), range.next());
+        assert_eq!(Some(&4), range.next_back());
+        assert_eq!(None, range.next());
+        assert_eq!(None, range.next_back());
+    }
+
+    #[test]
+    fn range_on_empty_tree() {
+        let bt: BinaryTree<i32> = BinaryTree::new();
+        assert_eq!(None, bt.range(..).next());
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_backwards_panics() {
+        let bt = BinaryTree::from_sorted(&[1, 2, 3]);
+        bt.range((Bound::Included(3), Bound::Included(1)));
+    }
+
+    #[test]
+    fn count_range() {
+        let bt = BinaryTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(4, bt.count_range(2..6));
+        assert_eq!(0, bt.count_range(10..));
+    }
+
+    #[test]
+    fn preorder() {
+        assert_eq!(vec![4, 2, 1, 3, 6, 5, 7], seven().preorder().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn postorder() {
+        assert_eq!(vec![1, 3, 2, 5, 7, 6, 4], seven().postorder().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn level_order() {
+        assert_eq!(vec![4, 2, 6, 1, 3, 5, 7], seven().level_order().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn for_loop_over_reference() {
+        let bt = seven();
+        let mut sum = 0;
+        for i in &bt {
+            sum += *i;
+        }
+        assert_eq!(28, sum);
+    }
+
+    #[test]
+    fn into_iter_drains_in_order() {
+        let bt = BinaryTree::from(&mut [String::from("b"), String::from("c"), String::from("a")]);
+        let drained: Vec<String> = bt.into_iter().collect();
+        assert_eq!(vec!["a", "b", "c"], drained);
+    }
+
+    #[test]
+    fn extract_if_yields_matches_in_order() {
+        let mut tree: AvlTree<String> = (0..20).map(|i| i.to_string()).collect();
+        let extracted: Vec<String> = tree.extract_if(|s| s.len() == 1).collect();
+        assert_eq!(vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"], extracted);
+        assert_eq!(Ok(()), tree.validate());
+        assert_eq!(10, tree.len());
+        assert!(tree.iter().all(|s| s.len() == 2));
+    }
+
+    #[test]
+    fn extract_if_leaves_values_it_never_reached() {
+        let mut tree: RedBlackTree<u32> = (0..100).collect();
+        {
+            let mut evens = tree.extract_if(|i| i % 2 == 0);
+            assert_eq!(Some(0), evens.next());
+            assert_eq!(Some(2), evens.next());
+        }
+        assert_eq!(Ok(()), tree.validate());
+        assert_eq!(98, tree.len());
+        assert!(tree.iter().cloned().eq((1..2).chain(3..100)));
+    }
+
+    #[test]
+    fn extract_if_keeps_the_value_pred_panics_on() {
+        let mut tree: AvlTree<u32> = (0..10).collect();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            tree.extract_if(|&i| if i == 5 { panic!("five") } else { i % 2 == 0 }).count()
+        }));
+        assert!(result.is_err());
+        assert_eq!(Ok(()), tree.validate());
+        assert!(tree.iter().cloned().eq(vec![1, 3, 5, 6, 7, 8, 9]));
+    }
+
+    #[test]
+    fn extract_if_nothing() {
+        let mut tree = seven();
+        assert_eq!(0, tree.extract_if(|_| false).count());
+        assert!(tree.iter().cloned().eq(1..8));
+        let mut empty: BinaryTree<i32> = BinaryTree::new();
+        assert_eq!(None, empty.extract_if(|_| true).next());
+        assert!(empty.is_empty());
+    }
+}
@@ -1,137 +1,938 @@
-#[derive(Debug,PartialEq)]
-pub struct BinaryTree<T : Ord> {
-    data: T,
-    left: Option<Box<BinaryTree<T>>>,
-    right: Option<Box<BinaryTree<T>>>,
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::RangeBounds;
+
+// Defines a test that runs `fixture`, a function generic over the balancing
+// strategy, once for each strategy.
+#[cfg(test)]
+macro_rules! test_each_balance {
+    ($name:ident, $fixture:ident) => {
+        #[test]
+        fn $name() {
+            $fixture::<::Unbalanced>();
+            $fixture::<::Avl>();
+            $fixture::<::RedBlack>();
+        }
+    }
 }
 
+mod avl;
+mod balance;
+mod compare;
+mod cursor;
+mod error;
+mod iter;
+pub mod map;
+pub mod multiset;
+mod node;
+mod rb;
+mod set;
+pub mod unchecked;
+
+pub use avl::Avl;
+pub use balance::{Balance, Unbalanced};
+pub use compare::{by_key, ByKey, Compare, Natural, Reverse};
+pub use cursor::{Cursor, CursorMut};
+pub use error::{BranchError, FromSortedError, InvariantViolation, Side, ViolationKind};
+pub use iter::{ExtractIf, IntoIter, Iter, LevelOrder, Postorder, Preorder, Range};
+pub use map::{AvlTreeMap, BinaryTreeMap, RedBlackTreeMap, SearchTreeMap};
+pub use multiset::{AvlTreeMultiSet, BinaryTreeMultiSet, RedBlackTreeMultiSet, SearchTreeMultiSet};
+pub use rb::RedBlack;
+pub use set::{Difference, Intersection, SymmetricDifference, Union};
+
+use cursor::Position;
+use node::{Link, Node};
+use set::Merge;
+
+/// A binary search tree holding a set of values, kept in shape by the
+/// balancing strategy `B`.
+///
+/// Use one of the aliases rather than naming `B` directly: `BinaryTree`
+/// never rebalances, while `AvlTree` and `RedBlackTree` keep their height
+/// logarithmic whatever order values are inserted in. All three share the
+/// same API, so code generic over `B` can be run against each of them.
+///
+/// Values are kept sorted by the comparator `C`, their natural order unless
+/// the tree is made by `with_comparator`.
+///
+/// Two trees are equal when they hold equal values, whatever their shapes.
+pub struct SearchTree<T, B : Balance, C = Natural> {
+    root: Link<T>,
+    len: usize,
+    balance: PhantomData<B>,
+    compare: C,
+}
+
+/// A plain binary search tree whose shape follows the order values were
+/// inserted in.
+pub type BinaryTree<T, C = Natural> = SearchTree<T, Unbalanced, C>;
+
+/// A binary search tree that rebalances itself as an AVL tree.
+pub type AvlTree<T, C = Natural> = SearchTree<T, Avl, C>;
+
+/// A binary search tree that rebalances itself as a red-black tree.
+pub type RedBlackTree<T, C = Natural> = SearchTree<T, RedBlack, C>;
+
 impl <T : Ord> BinaryTree<T> {
     pub fn leaf(data: T) -> BinaryTree<T> {
-        return BinaryTree{ data: data, left: None, right: None };
+        BinaryTree::with_root(Some(Box::new(Node::leaf(data))), 1, Natural)
     }
+
+    /// Makes a tree with `data` at its root over `left`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `left` holds a value that isn't less than `data`. See
+    /// `try_left`, or `unchecked::left` to skip the check.
     pub fn left(data: T, left: BinaryTree<T>) -> BinaryTree<T> {
-        return BinaryTree{ data: data, left: Some(Box::new(left)), right: None };
+        BinaryTree::try_left(data, left).unwrap_or_else(|error| panic!("{} in BinaryTree::left", error))
     }
+
+    /// Makes a tree with `data` at its root over `right`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `right` holds a value that isn't greater than `data`. See
+    /// `try_right`, or `unchecked::right` to skip the check.
     pub fn right(data: T, right: BinaryTree<T>) -> BinaryTree<T> {
-        return BinaryTree{ data: data, right: Some(Box::new(right)), left: None };
+        BinaryTree::try_right(data, right).unwrap_or_else(|error| panic!("{} in BinaryTree::right", error))
     }
+
+    /// Makes a tree with `data` at its root over `left` and `right`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `left` holds a value that isn't less than `data`, or `right`
+    /// one that isn't greater. See `try_branch`, or `unchecked::branch` to
+    /// skip the check.
     pub fn branch(data: T, left: BinaryTree<T>, right: BinaryTree<T>) -> BinaryTree<T> {
-        return BinaryTree{ data: data, left: Some(Box::new(left)), right: Some(Box::new(right))};
+        BinaryTree::try_branch(data, left, right).unwrap_or_else(|error| panic!("{} in BinaryTree::branch", error))
     }
 
-    pub fn contains(&self, value: T) -> bool {
-        if value == self.data {
-            return true;
-        } else if value > self.data {
-            return match self.right {
-                Some(ref right) => (*right).contains(value),
-                _ => false
-            };
-        } else if value < self.data {
-            return match self.left {
-                Some(ref left) => (*left).contains(value),
-                _ => false
-            };
+    /// Makes a tree with `data` at its root over `left`, if every value in
+    /// `left` is less than `data`.
+    pub fn try_left(data: T, left: BinaryTree<T>) -> Result<BinaryTree<T>, BranchError> {
+        BinaryTree::try_branch(data, left, BinaryTree::new())
+    }
+
+    /// Makes a tree with `data` at its root over `right`, if every value in
+    /// `right` is greater than `data`.
+    pub fn try_right(data: T, right: BinaryTree<T>) -> Result<BinaryTree<T>, BranchError> {
+        BinaryTree::try_branch(data, BinaryTree::new(), right)
+    }
+
+    /// Makes a tree with `data` at its root over `left` and `right`, if every
+    /// value in `left` is less than `data` and every value in `right` greater.
+    ///
+    /// Only the subtrees' extremes are compared with `data`, so the subtrees
+    /// are trusted to be in order themselves, as any tree made without the
+    /// `unchecked` constructors is.
+    pub fn try_branch(data: T, left: BinaryTree<T>, right: BinaryTree<T>) -> Result<BinaryTree<T>, BranchError> {
+        if left.last().is_some_and(|last| *last >= data) {
+            return Err(BranchError::LeftNotLess);
+        }
+        if right.first().is_some_and(|first| *first <= data) {
+            return Err(BranchError::RightNotGreater);
         }
+        Ok(unchecked::branch(data, left, right))
+    }
+}
 
-        false
+impl <T, B : Balance> SearchTree<T, B> {
+    /// Makes a new, empty tree that keeps its values in their natural order.
+    pub fn new() -> SearchTree<T, B> {
+        SearchTree::with_comparator(Natural)
     }
+}
 
-    pub fn insert(&mut self, value: T) {
-        if value == self.data {
-            return; // already in the set, no need to add it again. Or panic?
-        } else if value > self.data {
-            match self.right {
-                Some(ref mut right) => right.insert(value),
-                _ => self.right = Some(Box::new(BinaryTree::leaf(value)))
-            };
-        } else if value < self.data {
-            match self.left {
-                Some(ref mut left) => left.insert(value),
-                _ => self.left = Some(Box::new(BinaryTree::leaf(value)))
+impl <T, B : Balance, C> SearchTree<T, B, C> {
+    /// Makes a new, empty tree that keeps its values in the order given by
+    /// `compare`.
+    pub fn with_comparator(compare: C) -> SearchTree<T, B, C> {
+        SearchTree::with_root(None, 0, compare)
+    }
+
+    fn with_root(root: Link<T>, len: usize, compare: C) -> SearchTree<T, B, C> {
+        SearchTree{ root, len, balance: PhantomData, compare }
+    }
+
+    /// Returns the comparator the tree is ordered by.
+    pub fn comparator(&self) -> &C {
+        &self.compare
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over the values in order, from smallest to largest.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self)
+    }
+
+    /// Keeps only the values `keep` returns `true` for, visiting them in
+    /// order. The others are removed one at a time, as by `remove`.
+    pub fn retain<F : FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        self.extract_if(|value| !keep(value)).for_each(drop);
+    }
+
+    /// Lazily removes the values `pred` returns `true` for, visiting them in
+    /// order, and yields them by value. Values the iterator hasn't reached
+    /// when it is dropped stay in the tree.
+    pub fn extract_if<F : FnMut(&T) -> bool>(&mut self, pred: F) -> ExtractIf<'_, T, B, C, F> {
+        ExtractIf::new(self, pred)
+    }
+
+    /// Iterates over the values in pre-order: each node before its children.
+    pub fn preorder(&self) -> Preorder<'_, T> {
+        Preorder::new(self)
+    }
+
+    /// Iterates over the values in post-order: each node after its children.
+    pub fn postorder(&self) -> Postorder<'_, T> {
+        Postorder::new(self)
+    }
+
+    /// Iterates over the values level by level, starting at the root.
+    pub fn level_order(&self) -> LevelOrder<'_, T> {
+        LevelOrder::new(self)
+    }
+
+    /// Returns the smallest value in the tree.
+    pub fn first(&self) -> Option<&T> {
+        self.first_where(|_| true)
+    }
+
+    /// Returns the largest value in the tree.
+    pub fn last(&self) -> Option<&T> {
+        self.last_where(|_| true)
+    }
+
+    /// Returns the `k`th smallest value in the tree, counting from zero, in
+    /// time proportional to the tree's height.
+    pub fn select(&self, mut k: usize) -> Option<&T> {
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            let left = node::size(&node.left);
+            match k.cmp(&left) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(&node.data),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        None
+    }
+
+    // The building blocks shared with `SearchTreeMap`, which orders its
+    // entries by key. `probe` orders the value being looked for against a
+    // node's data, and `order` orders two values. Both are handed the tree's
+    // comparator, which they can't borrow from the tree themselves while it
+    // is being changed.
+
+    fn find_by<F : Fn(&C, &T) -> Ordering>(&self, probe: F) -> Option<&T> {
+        let compare = &self.compare;
+        match self.root {
+            Some(ref root) => root.find(&|data: &T| probe(compare, data)).map(|node| &node.data),
+            None => None
+        }
+    }
+
+    fn find_mut_by<F : Fn(&C, &T) -> Ordering>(&mut self, probe: F) -> Option<&mut T> {
+        let compare = &self.compare;
+        match self.root {
+            Some(ref mut root) => root.find_mut(&|data: &T| probe(compare, data)).map(|node| &mut node.data),
+            None => None
+        }
+    }
+
+    // Returns the rank of the value `probe` matches, if there is one.
+    fn rank_by<F : Fn(&C, &T) -> Ordering>(&self, probe: F) -> Option<usize> {
+        let mut rank = 0;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            match probe(&self.compare, &node.data) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(rank + node::size(&node.left)),
+                Ordering::Greater => {
+                    rank += node::size(&node.left) + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        None
+    }
+
+    // As `select`, but for changing the value in place.
+    fn select_mut(&mut self, mut k: usize) -> Option<&mut T> {
+        let mut link = &mut self.root;
+        while let Some(ref mut node) = *link {
+            let left = node::size(&node.left);
+            match k.cmp(&left) {
+                Ordering::Less => link = &mut node.left,
+                Ordering::Equal => return Some(&mut node.data),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    link = &mut node.right;
+                }
+            }
+        }
+        None
+    }
+
+    // Finds the value `probe` matches, as `find_mut_by` does, but hands the
+    // tree back when there is none, so that the caller can go on to insert
+    // without the borrow of the search getting in the way.
+    fn find_mut_or_self<F : Fn(&C, &T) -> Ordering>(&mut self, probe: F) -> Result<&mut T, &mut Self> {
+        match self.rank_by(probe) {
+            Some(rank) => Ok(self.select_mut(rank).unwrap()),
+            None => Err(self)
+        }
+    }
+
+    // Inserts `value` where `order` places it, returning its rank, which
+    // `select_mut` can use to find it again without comparing values. If an
+    // equal value is already in the tree, both are handed to `on_equal`
+    // instead.
+    fn insert_by<F, E>(&mut self, value: T, order: F, on_equal: E) -> Option<usize>
+            where F : Fn(&C, &T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+        let compare = &self.compare;
+        let added = B::insert(&mut self.root, value, &|a: &T, b: &T| order(compare, a, b), on_equal);
+        if added.is_some() {
+            self.len += 1;
+        }
+        added
+    }
+
+    fn remove_by<F : Fn(&C, &T) -> Ordering>(&mut self, probe: F) -> Option<T> {
+        let compare = &self.compare;
+        let removed = B::remove(&mut self.root, &mut |node: &Node<T>| probe(compare, &node.data));
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    // Removes the value at `index` in order, finding the way down to it by
+    // the cached sizes rather than by comparing values.
+    fn remove_at(&mut self, mut index: usize) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+        // The index is in range, so every node is asked about just once.
+        let removed = B::remove(&mut self.root, &mut |node: &Node<T>| {
+            let left = node::size(&node.left);
+            match index.cmp(&left) {
+                Ordering::Greater => {
+                    index -= left + 1;
+                    Ordering::Greater
+                },
+                ordering => ordering
+            }
+        });
+        self.len -= 1;
+        removed
+    }
+
+    // Finds the smallest value matching `pred`, which must hold for every
+    // value after the first one it holds for.
+    fn first_where<F : Fn(&T) -> bool>(&self, pred: F) -> Option<&T> {
+        let mut found = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if pred(&node.data) {
+                found = Some(&node.data);
+                link = &node.left;
+            } else {
+                link = &node.right;
+            }
+        }
+        found
+    }
+
+    // Finds the largest value matching `pred`, which must hold for every
+    // value before the last one it holds for.
+    fn last_where<F : Fn(&T) -> bool>(&self, pred: F) -> Option<&T> {
+        let mut found = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if pred(&node.data) {
+                found = Some(&node.data);
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+        found
+    }
+}
+
+impl <T, B : Balance, C : Compare<T>> SearchTree<T, B, C> {
+    /// Returns `true` if the tree holds a value equal to `value`, which may
+    /// be any borrowed form of the tree's values the comparator can order.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.find_by(|compare, data| compare.compare(value, data.borrow())).is_some()
+    }
+
+    /// Returns the number of values in the tree less than `value`, in time
+    /// proportional to the tree's height. This is the position `value` has,
+    /// or would have, in sorted order.
+    pub fn rank<Q>(&self, value: &Q) -> usize
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        let mut rank = 0;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if self.compare.compare(node.data.borrow(), value) == Ordering::Less {
+                rank += node::size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+        rank
+    }
+
+    /// Adds `value` to the tree, returning `true` if it was not already
+    /// present. An equal value already in the tree is left as it is.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.insert_by(value, C::compare, |_, _| {}).is_some()
+    }
+
+    /// Adds `value` to the tree, replacing and returning an equal value that
+    /// was already present.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        let mut replaced = None;
+        self.insert_by(value, C::compare, |data, value| replaced = Some(mem::replace(data, value)));
+        replaced
+    }
+
+    /// Removes and returns the value equal to `value`, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.remove(value)
+    }
+
+    /// Removes `value` from the tree, returning it if it was present.
+    ///
+    /// A node with two children is replaced by its in-order successor. Removing
+    /// the last value leaves the tree empty.
+    pub fn remove<Q>(&mut self, value: &Q) -> Option<T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.remove_by(|compare, data| compare.compare(value, data.borrow()))
+    }
+
+    /// Returns a cursor pointing at the least value greater than or equal to
+    /// `value`, or at the ghost position if there is none.
+    pub fn lower_bound_cursor<Q>(&self, value: &Q) -> Cursor<'_, T, B, C>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        let position = Position::lower_bound(&self.root, |data| self.compare.compare(value, data.borrow()));
+        Cursor::new(self, position)
+    }
+
+    /// Returns a cursor that can also insert and remove values, pointing at
+    /// the least value greater than or equal to `value`, or at the ghost
+    /// position if there is none.
+    pub fn lower_bound_cursor_mut<Q>(&mut self, value: &Q) -> CursorMut<'_, T, B, C>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        let position = Position::lower_bound(&self.root, |data| self.compare.compare(value, data.borrow()));
+        CursorMut::new(self, position)
+    }
+
+    /// Returns the greatest value less than or equal to `value`.
+    pub fn floor<Q>(&self, value: &Q) -> Option<&T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.last_where(|data| self.compare.compare(data.borrow(), value) != Ordering::Greater)
+    }
+
+    /// Returns the least value greater than or equal to `value`.
+    pub fn ceiling<Q>(&self, value: &Q) -> Option<&T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.first_where(|data| self.compare.compare(data.borrow(), value) != Ordering::Less)
+    }
+
+    /// Returns the greatest value strictly less than `value`.
+    pub fn lower<Q>(&self, value: &Q) -> Option<&T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.last_where(|data| self.compare.compare(data.borrow(), value) == Ordering::Less)
+    }
+
+    /// Returns the least value strictly greater than `value`.
+    pub fn higher<Q>(&self, value: &Q) -> Option<&T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> {
+        self.first_where(|data| self.compare.compare(data.borrow(), value) == Ordering::Greater)
+    }
+
+    /// Iterates in order over the values within `range`, skipping subtrees
+    /// that lie entirely outside of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends, or if it starts and ends at
+    /// the same excluded value.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, T>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q>, R : RangeBounds<Q> {
+        Range::new(self, range)
+    }
+
+    /// Counts the values within `range`.
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q>, R : RangeBounds<Q> {
+        self.range(range).count()
+    }
+
+    /// Iterates in order over the values in this tree, in `other`, or in
+    /// both. Values in both are yielded once, from this tree.
+    pub fn union<'a>(&'a self, other: &'a SearchTree<T, B, C>) -> Union<'a, T, C> {
+        Union::new(self.iter(), other.iter(), &self.compare)
+    }
+
+    /// Iterates in order over the values in both this tree and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a SearchTree<T, B, C>) -> Intersection<'a, T, C> {
+        Intersection::new(self.iter(), other.iter(), &self.compare)
+    }
+
+    /// Iterates in order over the values in this tree but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a SearchTree<T, B, C>) -> Difference<'a, T, C> {
+        Difference::new(self.iter(), other.iter(), &self.compare)
+    }
+
+    /// Iterates in order over the values in this tree or in `other`, but not
+    /// in both.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a SearchTree<T, B, C>) -> SymmetricDifference<'a, T, C> {
+        SymmetricDifference::new(self.iter(), other.iter(), &self.compare)
+    }
+
+    /// Returns `true` if every value in this tree is also in `other`.
+    pub fn is_subset(&self, other: &SearchTree<T, B, C>) -> bool {
+        self.len <= other.len && self.difference(other).next().is_none()
+    }
+
+    /// Returns `true` if every value in `other` is also in this tree.
+    pub fn is_superset(&self, other: &SearchTree<T, B, C>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if no value is in both this tree and `other`.
+    pub fn is_disjoint(&self, other: &SearchTree<T, B, C>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    /// Makes a balanced tree of the values in this tree, in `other`, or in
+    /// both, moving them rather than cloning them. Of two equal values the
+    /// one from this tree is kept.
+    pub fn into_union(self, other: SearchTree<T, B, C>) -> SearchTree<T, B, C> {
+        self.combine(other, |a, b| a.or(b))
+    }
+
+    /// Makes a balanced tree of the values in both this tree and `other`,
+    /// keeping the ones from this tree.
+    pub fn into_intersection(self, other: SearchTree<T, B, C>) -> SearchTree<T, B, C> {
+        self.combine(other, |a, b| b.and(a))
+    }
+
+    /// Makes a balanced tree of the values in this tree but not in `other`.
+    pub fn into_difference(self, other: SearchTree<T, B, C>) -> SearchTree<T, B, C> {
+        self.combine(other, |a, b| if b.is_some() { None } else { a })
+    }
+
+    /// Makes a balanced tree of the values in this tree or in `other`, but
+    /// not in both.
+    pub fn into_symmetric_difference(self, other: SearchTree<T, B, C>) -> SearchTree<T, B, C> {
+        self.combine(other, |a, b| match (a, b) {
+            (Some(_), Some(_)) => None,
+            (a, b) => a.or(b)
+        })
+    }
+
+    fn combine<F>(mut self, mut other: SearchTree<T, B, C>, keep: F) -> SearchTree<T, B, C>
+            where F : Fn(Option<T>, Option<T>) -> Option<T> {
+        self.merge(&mut other, keep);
+        self
+    }
+
+    // Walks this tree and `other` in step, keeps whatever `keep` makes of each
+    // value or pair of equal values, and rebuilds this tree, balanced, out of
+    // what was kept. `other` is left empty.
+    fn merge<F>(&mut self, other: &mut SearchTree<T, B, C>, keep: F)
+            where F : Fn(Option<T>, Option<T>) -> Option<T> {
+        let ours = IntoIter::from_root(self.root.take(), mem::replace(&mut self.len, 0));
+        let theirs = IntoIter::from_root(other.root.take(), mem::replace(&mut other.len, 0));
+        let mut kept = Vec::new();
+        {
+            let mut merge = Merge::new(ours, theirs, &self.compare);
+            loop {
+                match merge.next() {
+                    (None, None) => break,
+                    (a, b) => kept.extend(keep(a, b))
+                }
+            }
+        }
+
+        let (root, len) = build_root::<T, B, C>(kept, &self.compare);
+        self.root = root;
+        self.len = len;
+    }
+
+    /// Moves every value not less than `key` out into a new tree, which is
+    /// returned. The balanced strategies cut the tree apart in time
+    /// logarithmic in its length.
+    pub fn split_off<Q>(&mut self, key: &Q) -> SearchTree<T, B, C>
+            where T : Borrow<Q>, Q : ?Sized, C : Compare<Q> + Clone {
+        let (left, right) = {
+            let compare = &self.compare;
+            split::<T, B, _>(self.root.take(), |data| compare.compare(data.borrow(), key) == Ordering::Less)
+        };
+        self.len = node::size(&left);
+        self.root = left;
+        let len = node::size(&right);
+        SearchTree::with_root(right, len, self.compare.clone())
+    }
+
+    /// Moves every value in `other` into this tree, leaving `other` empty. A
+    /// value in `other` replaces an equal one already here.
+    ///
+    /// When all of one tree's values are less than all of the other's the
+    /// two are joined in time logarithmic in their lengths, and otherwise
+    /// the values are merged into a new balanced tree.
+    pub fn append(&mut self, other: &mut SearchTree<T, B, C>) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            mem::swap(&mut self.root, &mut other.root);
+            mem::swap(&mut self.len, &mut other.len);
+            return;
+        }
+
+        let below = self.compare.compare(self.last().unwrap(), other.first().unwrap()) == Ordering::Less;
+        let above = self.compare.compare(other.last().unwrap(), self.first().unwrap()) == Ordering::Less;
+        let (lower, upper) = if below {
+            (self.root.take(), other.root.take())
+        } else if above {
+            (other.root.take(), self.root.take())
+        } else {
+            return self.merge(other, |a, b| b.or(a));
+        };
+
+        let (rest, last) = split_last::<T, B>(lower);
+        self.root = Some(B::join(rest, last, upper));
+        self.len += mem::replace(&mut other.len, 0);
+    }
+
+    /// Checks that every value lies strictly between the values of the
+    /// ancestors it sits between, that the cached sizes and the length add
+    /// up, and that the balancing strategy's bookkeeping is consistent. The
+    /// first violation found is returned along with the path to its node.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        // Walked depth first with an explicit stack, so that even a long
+        // chain of nodes can be checked. Each entry carries the bounds its
+        // node must lie between, the length `path` is cut back to before the
+        // step down to the node is added, and whether the node's children
+        // are done. Sizes are checked on the way back up, so that a wrong
+        // size is blamed on its own node rather than on its parent.
+        let mut path = Vec::new();
+        let mut stack = Vec::new();
+        if let Some(ref root) = self.root {
+            stack.push((&**root, None, None, 0, None, false));
+        }
+        while let Some((node, lower, upper, depth, side, children_done)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(side);
+
+            let size = 1 + node::size(&node.left) + node::size(&node.right);
+            let kind = if children_done {
+                if node.size == size {
+                    continue;
+                }
+                ViolationKind::WrongSize{ cached: node.size, actual: size }
+            } else {
+                let in_order = lower.is_none_or(|lower| self.compare.compare(lower, &node.data) == Ordering::Less)
+                    && upper.is_none_or(|upper| self.compare.compare(&node.data, upper) == Ordering::Less);
+                if in_order {
+                    stack.push((node, lower, upper, depth, side, true));
+                    if let Some(ref right) = node.right {
+                        stack.push((&**right, Some(&node.data), upper, path.len(), Some(Side::Right), false));
+                    }
+                    if let Some(ref left) = node.left {
+                        stack.push((&**left, lower, Some(&node.data), path.len(), Some(Side::Left), false));
+                    }
+                    continue;
+                }
+                ViolationKind::OutOfOrder
             };
+            return Err(InvariantViolation{ path, kind });
+        }
+
+        let nodes = node::size(&self.root);
+        if nodes != self.len {
+            return Err(InvariantViolation{ path: Vec::new(), kind: ViolationKind::WrongLength{ len: self.len, nodes } });
         }
+        B::validate(&self.root, &mut Vec::new())
+    }
+}
+
+impl <T, B : Balance, C> Drop for SearchTree<T, B, C> {
+    fn drop(&mut self) {
+        node::tear_down(self.root.take());
     }
+}
 
+// Both go through `iter` rather than recursing down the tree, so that they
+// cope with trees of any depth.
+impl <T : PartialEq, B : Balance, C> PartialEq for SearchTree<T, B, C> {
+    fn eq(&self, other: &SearchTree<T, B, C>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
 }
 
-impl <T : Ord + Clone> BinaryTree<T> {
-    pub fn from(data: &mut [T]) -> BinaryTree<T> {
-        data.sort();
-        return BinaryTree::from_sorted(data);
+impl <T : fmt::Debug, B : Balance, C> fmt::Debug for SearchTree<T, B, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
     }
+}
 
-    pub fn from_sorted(data: &[T]) -> BinaryTree<T> {
-        let len = data.len();
-        if len == 0 {
-            panic!("cannot make a binary tree out of no items");
+impl <T, B : Balance, C : Default> Default for SearchTree<T, B, C> {
+    fn default() -> SearchTree<T, B, C> {
+        SearchTree::with_comparator(C::default())
+    }
+}
+
+// Builds a balanced tree out of `values`, keeping the first of any equal
+// values, and returns its root and length. Input that is already sorted
+// without duplicates is spotted and built straight away.
+fn build_root<T, B : Balance, C : Compare<T>>(mut values: Vec<T>, compare: &C) -> (Link<T>, usize) {
+    let sorted = values.windows(2).all(|pair| compare.compare(&pair[0], &pair[1]) == Ordering::Less);
+    if !sorted {
+        // The sort is stable, so the value kept from a run of equal ones is
+        // the one that came first.
+        values.sort_by(|a, b| compare.compare(a, b));
+        values.dedup_by(|later, earlier| compare.compare(later, earlier) == Ordering::Equal);
+    }
+
+    let len = values.len();
+    let mut root = Node::from_sorted_iter(values.into_iter(), len);
+    B::build(&mut root, len);
+    (root, len)
+}
+
+// Cuts the tree below `link` in two, the values `goes_left` picks out and the
+// rest, which must all be greater. Each node on the way down the cut is
+// joined back onto one side or the other on the way up.
+fn split<T, B : Balance, F : Fn(&T) -> bool>(mut link: Link<T>, goes_left: F) -> (Link<T>, Link<T>) {
+    let mut path = Vec::new();
+    while let Some(mut node) = link {
+        let left = goes_left(&node.data);
+        link = if left { node.right.take() } else { node.left.take() };
+        path.push((node, left));
+    }
+    rejoin::<T, B>(path, None, None)
+}
+
+// Cuts the greatest value's node off the non-empty tree below `link`,
+// returning what is left along with it.
+fn split_last<T, B : Balance>(mut link: Link<T>) -> (Link<T>, Box<Node<T>>) {
+    let mut path = Vec::new();
+    while let Some(mut node) = link {
+        link = node.right.take();
+        path.push((node, true));
+    }
+    let (mut last, _) = path.pop().expect("splitting the last value off an empty tree");
+    let rest = last.left.take();
+    (rejoin::<T, B>(path, rest, None).0, last)
+}
+
+// Joins the nodes on `path`, each stripped of the child that leads further
+// down it, back onto `left` or `right` from the bottom up, according to the
+// side each is marked for.
+fn rejoin<T, B : Balance>(mut path: Vec<(Box<Node<T>>, bool)>, mut left: Link<T>, mut right: Link<T>)
+        -> (Link<T>, Link<T>) {
+    while let Some((mut node, goes_left)) = path.pop() {
+        if goes_left {
+            let outer = node.left.take();
+            left = Some(B::join(outer, node, left));
+        } else {
+            let outer = node.right.take();
+            right = Some(B::join(right, node, outer));
         }
+    }
+    (left, right)
+}
 
-        // integer division by 2
-        let pivot = len >> 1;
-        let mut tree = BinaryTree::leaf(data[pivot].clone());
+impl <T, B : Balance, C : Compare<T> + Default> FromIterator<T> for SearchTree<T, B, C> {
+    /// Builds a balanced tree out of `iter`'s values, which are moved in
+    /// rather than cloned. Of any equal values only the first is kept.
+    fn from_iter<I : IntoIterator<Item = T>>(iter: I) -> SearchTree<T, B, C> {
+        let compare = C::default();
+        let (root, len) = build_root::<T, B, C>(iter.into_iter().collect(), &compare);
+        SearchTree::with_root(root, len, compare)
+    }
+}
 
-        if pivot > 0 {
-            tree.left = Some(Box::new(BinaryTree::from_sorted(&data[0..pivot])));
+impl <T, B : Balance, C : Compare<T>> Extend<T> for SearchTree<T, B, C> {
+    /// Inserts each of `iter`'s values, keeping the values already in the
+    /// tree over equal ones. An empty tree is built balanced in one go, as
+    /// by `collect`.
+    fn extend<I : IntoIterator<Item = T>>(&mut self, iter: I) {
+        if self.is_empty() {
+            let (root, len) = build_root::<T, B, C>(iter.into_iter().collect(), &self.compare);
+            self.root = root;
+            self.len = len;
+        } else {
+            for value in iter {
+                self.insert(value);
+            }
         }
+    }
+}
+
+impl <T : Ord + Clone, B : Balance> SearchTree<T, B> {
+    pub fn from(data: &mut [T]) -> SearchTree<T, B> {
+        data.sort();
+        SearchTree::from_sorted(data)
+    }
+
+    /// Builds a balanced tree out of already sorted, deduplicated values. An
+    /// empty slice makes an empty tree.
+    ///
+    /// The input is trusted rather than checked: values out of order or
+    /// repeated make a tree that can't find them. See `try_from_sorted`.
+    pub fn from_sorted(data: &[T]) -> SearchTree<T, B> {
+        SearchTree::from_sorted_iter(data.iter().cloned())
+    }
 
-        if pivot + 1 < data.len() {
-            tree.right = Some(Box::new(BinaryTree::from_sorted(&data[(pivot + 1)..data.len()])));
+    /// Like `from_sorted`, but first checks that `data` is non-empty, sorted
+    /// and free of duplicates.
+    pub fn try_from_sorted(data: &[T]) -> Result<SearchTree<T, B>, FromSortedError> {
+        check_sorted(data)?;
+        Ok(SearchTree::from_sorted(data))
+    }
+}
+
+impl <T : Ord, B : Balance> SearchTree<T, B> {
+    /// Like `from_sorted_vec`, but first checks that `data` is non-empty,
+    /// sorted and free of duplicates.
+    pub fn try_from_sorted_vec(data: Vec<T>) -> Result<SearchTree<T, B>, FromSortedError> {
+        check_sorted(&data)?;
+        Ok(SearchTree::from_sorted_vec(data))
+    }
+}
+
+fn check_sorted<T : Ord>(data: &[T]) -> Result<(), FromSortedError> {
+    if data.is_empty() {
+        return Err(FromSortedError::Empty);
+    }
+    for (i, pair) in data.windows(2).enumerate() {
+        match pair[0].cmp(&pair[1]) {
+            Ordering::Less => {},
+            Ordering::Equal => return Err(FromSortedError::Duplicate{ index: i + 1 }),
+            Ordering::Greater => return Err(FromSortedError::NotSorted{ index: i + 1 })
         }
+    }
+    Ok(())
+}
+
+impl <T, B : Balance> SearchTree<T, B> {
+    /// Builds a balanced tree out of already sorted, deduplicated values,
+    /// moving them out of `data` rather than cloning them.
+    pub fn from_sorted_vec(data: Vec<T>) -> SearchTree<T, B> {
+        SearchTree::from_sorted_iter(data.into_iter())
+    }
 
-        return tree;
+    /// Builds a balanced tree out of the already sorted, deduplicated values
+    /// `data` yields, splitting them at the same midpoints as `from_sorted`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` yields fewer values than its `len` promised.
+    pub fn from_sorted_iter<I : ExactSizeIterator<Item = T>>(data: I) -> SearchTree<T, B> {
+        let len = data.len();
+        let mut root = Node::from_sorted_iter(data, len);
+        B::build(&mut root, len);
+        SearchTree::with_root(root, len, Natural)
     }
 }
 
 #[cfg(test)]
+// The original tests predate these lints and are kept as they were written.
+#[allow(clippy::assertions_on_constants, clippy::needless_range_loop)]
 mod tests {
 
-    use BinaryTree;
+    use std::cmp::Ordering;
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+
+    use {by_key, unchecked, AvlTree, BinaryTree, BranchError, FromSortedError, Natural, RedBlackTree, Reverse};
+    use {Balance, SearchTree, Side, ViolationKind};
+    use node::{Link, Node};
+
+    fn height<T>(link: &Link<T>) -> usize {
+        match *link {
+            Some(ref node) => 1 + height(&node.left).max(height(&node.right)),
+            None => 0
+        }
+    }
+
+    fn root<T>(mut bt: BinaryTree<T>) -> Node<T> {
+        *bt.root.take().expect("tree should not be empty")
+    }
 
     #[test]
     fn bt_leaf() {
-        let bt = BinaryTree::leaf(5);
+        let bt = root(BinaryTree::leaf(5));
         assert_eq!(5, bt.data);
-        assert_eq!(None as Option<Box<BinaryTree<_>>>, bt.left);
-        assert_eq!(None as Option<Box<BinaryTree<_>>>, bt.right);
+        assert_eq!(None as Option<Box<Node<_>>>, bt.left);
+        assert_eq!(None as Option<Box<Node<_>>>, bt.right);
     }
 
     #[test]
     fn bt_leftonly() {
         let l = BinaryTree::leaf(1);
-        let bt = BinaryTree::left(5, l);
+        let bt = root(BinaryTree::left(5, l));
         assert_eq!(5, bt.data);
         match bt.left {
-            Some(btl) => assert_eq!(BinaryTree::leaf(1), *btl),
+            Some(btl) => assert_eq!(root(BinaryTree::leaf(1)), *btl),
             _ => assert!(false)
         }
-        assert_eq!(None as Option<Box<BinaryTree<_>>>, bt.right);
+        assert_eq!(None as Option<Box<Node<_>>>, bt.right);
     }
 
     #[test]
     fn bt_rightonly() {
         let r = BinaryTree::leaf(10);
-        let bt = BinaryTree::right(5, r);
+        let bt = root(BinaryTree::right(5, r));
         assert_eq!(5, bt.data);
         match bt.right {
-            Some(btr) => assert_eq!(BinaryTree::leaf(10), *btr),
+            Some(btr) => assert_eq!(root(BinaryTree::leaf(10)), *btr),
             _ => assert!(false)
         }
-        assert_eq!(None as Option<Box<BinaryTree<_>>>, bt.left);
+        assert_eq!(None as Option<Box<Node<_>>>, bt.left);
     }
 
     #[test]
     fn bt_branch() {
         let l = BinaryTree::leaf(1);
         let r = BinaryTree::leaf(10);
-        let bt = BinaryTree::branch(5, l, r);
+        let bt = root(BinaryTree::branch(5, l, r));
         assert_eq!(5, bt.data);
         match bt.left {
-            Some(btl) => assert_eq!(BinaryTree::leaf(1), *btl),
+            Some(btl) => assert_eq!(root(BinaryTree::leaf(1)), *btl),
             _ => assert!(false)
         }
         match bt.right {
-            Some(btr) => assert_eq!(BinaryTree::leaf(10), *btr),
+            Some(btr) => assert_eq!(root(BinaryTree::leaf(10)), *btr),
             _ => assert!(false)
         }
     }
@@ -139,41 +940,41 @@ mod tests {
     #[test]
     fn leaf_contains_true() {
         let bt = BinaryTree::leaf(5);
-        assert!(bt.contains(5));
+        assert!(bt.contains(&5));
     }
 
     #[test]
     fn leaf_contains_false_going_right() {
         let bt = BinaryTree::leaf(5);
-        assert!(!bt.contains(6));
+        assert!(!bt.contains(&6));
     }
 
     #[test]
     fn leaf_contains_false_going_left() {
         let bt = BinaryTree::leaf(5);
-        assert!(!bt.contains(4));
+        assert!(!bt.contains(&4));
     }
 
     #[test]
     fn branch_contains_goes_left() {
         let l = BinaryTree::leaf(1);
         let bt = BinaryTree::left(5, l);
-        assert!(bt.contains(1));
+        assert!(bt.contains(&1));
     }
 
     #[test]
     fn branch_contains_goes_right() {
         let l = BinaryTree::leaf(10);
         let bt = BinaryTree::right(5, l);
-        assert!(bt.contains(10));
+        assert!(bt.contains(&10));
     }
 
     #[test]
     fn leaf_inserts_left() {
         let mut bt = BinaryTree::leaf(5);
         bt.insert(1);
-        match bt.left {
-            Some(btl) => assert_eq!(BinaryTree::leaf(1), *btl),
+        match root(bt).left {
+            Some(btl) => assert_eq!(root(BinaryTree::leaf(1)), *btl),
             _ => assert!(false)
         }
     }
@@ -182,12 +983,631 @@ mod tests {
     fn leaf_inserts_right() {
         let mut bt = BinaryTree::leaf(5);
         bt.insert(10);
-        match bt.right {
-            Some(btr) => assert_eq!(BinaryTree::leaf(10), *btr),
+        match root(bt).right {
+            Some(btr) => assert_eq!(root(BinaryTree::leaf(10)), *btr),
             _ => assert!(false)
         }
     }
 
+    #[test]
+    fn remove_leaf() {
+        let mut bt = BinaryTree::branch(5, BinaryTree::leaf(1), BinaryTree::leaf(10));
+        assert_eq!(Some(1), bt.remove(&1));
+        assert!(!bt.contains(&1));
+        assert_eq!(BinaryTree::right(5, BinaryTree::leaf(10)), bt);
+    }
+
+    #[test]
+    fn remove_with_one_child() {
+        let mut bt = BinaryTree::left(5, BinaryTree::left(3, BinaryTree::leaf(1)));
+        assert_eq!(Some(3), bt.remove(&3));
+        assert_eq!(BinaryTree::left(5, BinaryTree::leaf(1)), bt);
+    }
+
+    #[test]
+    fn remove_with_two_children_uses_successor() {
+        let mut bt = BinaryTree::branch(5,
+            BinaryTree::leaf(1),
+            BinaryTree::left(10, BinaryTree::right(7, BinaryTree::leaf(8))));
+        assert_eq!(Some(5), bt.remove(&5));
+        assert_eq!(BinaryTree::branch(7,
+            BinaryTree::leaf(1),
+            BinaryTree::left(10, BinaryTree::leaf(8))), bt);
+    }
+
+    #[test]
+    fn remove_missing() {
+        let mut bt = BinaryTree::branch(5, BinaryTree::leaf(1), BinaryTree::leaf(10));
+        assert_eq!(None, bt.remove(&7));
+        assert_eq!(BinaryTree::branch(5, BinaryTree::leaf(1), BinaryTree::leaf(10)), bt);
+    }
+
+    #[test]
+    fn remove_root_leaves_tree_empty() {
+        let mut bt = BinaryTree::leaf(5);
+        assert_eq!(Some(5), bt.remove(&5));
+        assert!(!bt.contains(&5));
+        assert_eq!(None, bt.remove(&5));
+        bt.insert(3);
+        assert!(bt.contains(&3));
+    }
+
+    #[test]
+    fn new_is_empty() {
+        let bt: BinaryTree<i32> = BinaryTree::new();
+        assert!(bt.is_empty());
+        assert_eq!(0, bt.len());
+        assert!(!bt.contains(&1));
+        assert_eq!(BinaryTree::default(), bt);
+    }
+
+    #[test]
+    fn len_tracks_insert_and_remove() {
+        let mut bt = BinaryTree::new();
+        bt.insert(5);
+        bt.insert(1);
+        bt.insert(5);
+        assert_eq!(2, bt.len());
+        bt.remove(&7);
+        assert_eq!(2, bt.len());
+        bt.remove(&5);
+        bt.remove(&1);
+        assert!(bt.is_empty());
+    }
+
+    #[test]
+    fn from_empty_is_empty() {
+        let empty: [i32; 0] = [];
+        assert!(BinaryTree::from_sorted(&empty).is_empty());
+        assert!(BinaryTree::<i32>::from(&mut []).is_empty());
+        assert_eq!(3, BinaryTree::from(&mut [3, 1, 2]).len());
+    }
+
+    #[test]
+    fn first_and_last() {
+        let bt = BinaryTree::from(&mut [5, 3, 9, 1, 7]);
+        assert_eq!(Some(&1), bt.first());
+        assert_eq!(Some(&9), bt.last());
+
+        let empty: BinaryTree<i32> = BinaryTree::new();
+        assert_eq!(None, empty.first());
+        assert_eq!(None, empty.last());
+    }
+
+    #[test]
+    fn floor_and_ceiling() {
+        let bt = BinaryTree::from(&mut [10, 20, 30, 40]);
+        assert_eq!(Some(&20), bt.floor(&20));
+        assert_eq!(Some(&20), bt.floor(&25));
+        assert_eq!(None, bt.floor(&5));
+        assert_eq!(Some(&40), bt.floor(&99));
+        assert_eq!(Some(&20), bt.ceiling(&20));
+        assert_eq!(Some(&30), bt.ceiling(&25));
+        assert_eq!(Some(&10), bt.ceiling(&5));
+        assert_eq!(None, bt.ceiling(&41));
+    }
+
+    #[test]
+    fn lower_and_higher() {
+        let bt = BinaryTree::from(&mut [10, 20, 30, 40]);
+        assert_eq!(Some(&10), bt.lower(&20));
+        assert_eq!(Some(&20), bt.lower(&25));
+        assert_eq!(None, bt.lower(&10));
+        assert_eq!(Some(&30), bt.higher(&20));
+        assert_eq!(Some(&30), bt.higher(&25));
+        assert_eq!(None, bt.higher(&40));
+    }
+
+    #[test]
+    fn rank_and_select() {
+        let mut bt = BinaryTree::new();
+        for value in &[50, 20, 80, 10, 30, 70, 90, 60] {
+            bt.insert(*value);
+        }
+        let sorted = [10, 20, 30, 50, 60, 70, 80, 90];
+        for (i, value) in sorted.iter().enumerate() {
+            assert_eq!(i, bt.rank(value));
+            assert_eq!(Some(value), bt.select(i));
+        }
+        assert_eq!(0, bt.rank(&5));
+        assert_eq!(3, bt.rank(&40));
+        assert_eq!(8, bt.rank(&100));
+        assert_eq!(None, bt.select(8));
+
+        bt.remove(&50);
+        bt.remove(&10);
+        assert_eq!(Some(&60), bt.select(2));
+        assert_eq!(2, bt.rank(&60));
+    }
+
+    #[test]
+    fn rank_and_select_on_built_trees() {
+        let data: Vec<u32> = (0..100).map(|i| i * 3).collect();
+        let bt = BinaryTree::from_sorted(&data);
+        for (i, value) in data.iter().enumerate() {
+            assert_eq!(i, bt.rank(value));
+            assert_eq!(i + 1, bt.rank(&(value + 1)));
+            assert_eq!(Some(value), bt.select(i));
+        }
+        let branch = BinaryTree::branch(2, BinaryTree::leaf(1), BinaryTree::right(3, BinaryTree::leaf(4)));
+        assert_eq!(Some(&4), branch.select(3));
+        assert_eq!(3, branch.rank(&4));
+    }
+
+    #[test]
+    fn insert_reports_new_values() {
+        let mut bt = BinaryTree::new();
+        assert!(bt.insert(5));
+        assert!(bt.insert(3));
+        assert!(!bt.insert(5));
+        assert_eq!(2, bt.len());
+    }
+
+    // Orders by key alone, so equal values can carry different payloads.
+    #[derive(Debug)]
+    struct Keyed(u32, &'static str);
+
+    impl PartialEq for Keyed {
+        fn eq(&self, other: &Keyed) -> bool { self.0 == other.0 }
+    }
+
+    impl Eq for Keyed {}
+
+    impl PartialOrd for Keyed {
+        fn partial_cmp(&self, other: &Keyed) -> Option<Ordering> { Some(self.cmp(other)) }
+    }
+
+    impl Ord for Keyed {
+        fn cmp(&self, other: &Keyed) -> Ordering { self.0.cmp(&other.0) }
+    }
+
+    #[test]
+    fn insert_keeps_the_existing_value() {
+        let mut bt = BinaryTree::new();
+        bt.insert(Keyed(1, "old"));
+        assert!(!bt.insert(Keyed(1, "new")));
+        assert_eq!("old", bt.first().unwrap().1);
+    }
+
+    #[test]
+    fn replace_swaps_in_equal_values() {
+        let mut bt = BinaryTree::new();
+        assert!(bt.replace(Keyed(1, "old")).is_none());
+        bt.insert(Keyed(2, "other"));
+        assert_eq!("old", bt.replace(Keyed(1, "new")).unwrap().1);
+        assert_eq!("new", bt.first().unwrap().1);
+        assert_eq!(2, bt.len());
+    }
+
+    #[test]
+    fn take_removes_the_stored_value() {
+        let mut bt = BinaryTree::new();
+        bt.insert(Keyed(1, "stored"));
+        assert_eq!("stored", bt.take(&Keyed(1, "probe")).unwrap().1);
+        assert!(bt.take(&Keyed(1, "probe")).is_none());
+        assert!(bt.is_empty());
+    }
+
+    #[test]
+    fn reverse_comparator() {
+        let mut tree = AvlTree::with_comparator(Reverse(Natural));
+        for i in 0..10 {
+            tree.insert(i);
+        }
+        assert!(tree.iter().cloned().eq((0..10).rev()));
+        assert_eq!(Some(&9), tree.first());
+        assert_eq!(Some(&4), tree.higher(&5));
+        assert_eq!(2, tree.rank(&7));
+        assert!(tree.range((Bound::Included(7), Bound::Excluded(3))).cloned().eq(vec![7, 6, 5, 4]));
+    }
+
+    #[derive(Debug)]
+    struct Person {
+        name: &'static str,
+        age: u32,
+    }
+
+    #[test]
+    fn by_key_comparator() {
+        let mut tree = RedBlackTree::with_comparator(by_key(|p: &Person| p.age));
+        tree.insert(Person{ name: "ann", age: 40 });
+        tree.insert(Person{ name: "bob", age: 25 });
+        assert!(!tree.insert(Person{ name: "cat", age: 40 }));
+        let names: Vec<_> = tree.iter().map(|p| p.name).collect();
+        assert_eq!(vec!["bob", "ann"], names);
+        assert!(tree.contains(&Person{ name: "anyone", age: 25 }));
+    }
+
+    #[test]
+    fn closure_comparator() {
+        let mut tree = BinaryTree::with_comparator(|a: &&str, b: &&str| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        tree.insert("Banana");
+        tree.insert("apple");
+        assert!(!tree.insert("APPLE"));
+        assert!(tree.contains(&"BANANA"));
+        assert_eq!(Some(&"apple"), tree.first());
+    }
+
+    #[test]
+    fn lookups_take_borrowed_values() {
+        let mut tree = BinaryTree::new();
+        for word in &["pear", "apple", "fig"] {
+            tree.insert(word.to_string());
+        }
+        assert!(tree.contains("apple"));
+        assert!(!tree.contains("plum"));
+        assert_eq!(Some(&"fig".to_string()), tree.ceiling("banana"));
+        assert_eq!(1, tree.count_range::<str, _>((Bound::Included("b"), Bound::Excluded("g"))));
+        assert_eq!(Some("pear".to_string()), tree.remove("pear"));
+        assert_eq!(2, tree.len());
+    }
+
+    // As deep as inserting 0..1_000_000 in order makes it, without the
+    // quadratic cost of doing so.
+    fn right_chain(len: u32) -> BinaryTree<u32> {
+        let mut bt = BinaryTree::leaf(len - 1);
+        for i in (0..(len - 1)).rev() {
+            bt = BinaryTree::right(i, bt);
+        }
+        bt
+    }
+
+    #[test]
+    fn equality_ignores_shape() {
+        let mut a = BinaryTree::new();
+        a.insert(1);
+        a.insert(2);
+        let mut b = BinaryTree::new();
+        b.insert(2);
+        b.insert(1);
+        assert_eq!(a, b);
+        assert_eq!(a, AvlTree::from_sorted(&[1, 2]).into_iter().collect());
+        assert_eq!("{1, 2}", format!("{:?}", a));
+        b.insert(3);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn degenerate_chain_does_not_overflow() {
+        let mut bt = right_chain(1_000_000);
+        assert!(bt.contains(&999_999));
+        assert!(bt.insert(1_000_000));
+        assert!(!bt.insert(500_000));
+        assert_eq!(Some(999_999), bt.remove(&999_999));
+        assert_eq!(None, bt.remove(&999_999));
+        assert_eq!(1_000_000, bt.len());
+        assert_eq!(Some(&1_000_000), bt.select(999_999));
+        assert_eq!(Ok(()), bt.validate());
+        drop(bt);
+
+        let mut bt = BinaryTree::leaf(0);
+        for i in 1..1_000_000 {
+            bt = BinaryTree::left(i, bt);
+        }
+        assert!(bt.contains(&0));
+        assert_eq!(Ok(()), bt.validate());
+        drop(bt);
+
+        let bt = right_chain(1_000_000);
+        assert!(bt == right_chain(1_000_000));
+        assert!(bt != right_chain(999_999));
+        assert!(format!("{:?}", bt).ends_with(", 999998, 999999}"));
+        drop(bt);
+
+        let mut iter = right_chain(1_000_000).into_iter();
+        assert_eq!(Some(0), iter.next());
+        drop(iter);
+    }
+
+    #[test]
+    fn collect_sorted_input_is_balanced() {
+        let bt: BinaryTree<u32> = (0..1023).collect();
+        assert_eq!(1023, bt.len());
+        assert_eq!(Some(&511), bt.root.as_ref().map(|root| &root.data));
+        assert_eq!(10, height(&bt.root));
+        assert!(bt.iter().cloned().eq(0..1023));
+        assert!(bt.iter().enumerate().all(|(i, value)| bt.rank(value) == i));
+    }
+
+    #[test]
+    fn collect_sorts_and_keeps_first_duplicate() {
+        let bt: BinaryTree<Keyed> = vec![Keyed(3, "a"), Keyed(1, "b"), Keyed(3, "c"), Keyed(2, "d")]
+            .into_iter().collect();
+        assert_eq!(3, bt.len());
+        let payloads: Vec<_> = bt.iter().map(|k| k.1).collect();
+        assert_eq!(vec!["b", "d", "a"], payloads);
+        assert_eq!(7, height(&(0..100).rev().collect::<BinaryTree<u32>>().root));
+    }
+
+    #[test]
+    fn collect_moves_values() {
+        let words = vec!["pear".to_string(), "fig".to_string()];
+        let tree: RedBlackTree<String> = words.into_iter().collect();
+        assert!(tree.contains("fig"));
+        let empty: AvlTree<String> = Vec::new().into_iter().collect();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn extend_inserts_values() {
+        let mut bt = BinaryTree::new();
+        bt.extend(0..7);
+        assert_eq!(3, height(&bt.root));
+        bt.extend(vec![3, 10, 8]);
+        assert_eq!(9, bt.len());
+        assert!(bt.iter().cloned().eq((0..9).filter(|&i| i != 7).chain(Some(10))));
+    }
+
+    #[test]
+    fn from_sorted_vec_moves_values() {
+        let words: Vec<String> = ["ant", "bee", "cat", "dog", "eel"].iter().map(|w| w.to_string()).collect();
+        let cloned = BinaryTree::from_sorted(&words);
+        let moved = BinaryTree::from_sorted_vec(words);
+        assert_eq!(cloned, moved);
+        assert_eq!("cat", moved.root.as_ref().unwrap().data);
+        assert!(moved.contains("eel"));
+    }
+
+    #[test]
+    fn from_sorted_iter_matches_from_sorted() {
+        for len in 0..40 {
+            let data: Vec<u32> = (0..len).collect();
+            let built = AvlTree::from_sorted_iter(0..len);
+            assert_eq!(AvlTree::from_sorted(&data), built);
+            assert_eq!(len as usize, built.len());
+        }
+    }
+
+    #[test]
+    fn try_from_sorted_checks_input() {
+        let tree = RedBlackTree::try_from_sorted(&[1, 2, 5, 9]).unwrap();
+        assert!(tree.contains(&5));
+        assert_eq!(Err(FromSortedError::NotSorted{ index: 2 }), BinaryTree::try_from_sorted(&[1, 5, 2]));
+        assert_eq!(Err(FromSortedError::Duplicate{ index: 3 }), BinaryTree::try_from_sorted(&[1, 2, 3, 3]));
+        assert_eq!(Err(FromSortedError::Empty), BinaryTree::<u32>::try_from_sorted(&[]));
+        assert_eq!(Err(FromSortedError::NotSorted{ index: 1 }), AvlTree::try_from_sorted_vec(vec!["b", "a"]));
+        assert_eq!("value at index 1 is less than the one before it",
+                   FromSortedError::NotSorted{ index: 1 }.to_string());
+    }
+
+    #[test]
+    fn try_constructors_check_order() {
+        assert!(BinaryTree::try_left(5, BinaryTree::leaf(4)).is_ok());
+        assert_eq!(Err(BranchError::LeftNotLess), BinaryTree::try_left(5, BinaryTree::leaf(5)));
+        assert_eq!(Err(BranchError::RightNotGreater), BinaryTree::try_right(5, BinaryTree::leaf(1)));
+
+        // The deepest value of each subtree is the one that gives it away.
+        let left = BinaryTree::right(1, BinaryTree::right(2, BinaryTree::leaf(7)));
+        assert_eq!(Err(BranchError::LeftNotLess), BinaryTree::try_branch(5, left, BinaryTree::leaf(9)));
+        let right = BinaryTree::left(9, BinaryTree::left(8, BinaryTree::leaf(3)));
+        assert_eq!(Err(BranchError::RightNotGreater), BinaryTree::try_branch(5, BinaryTree::leaf(1), right));
+
+        let bt = BinaryTree::try_branch(5, BinaryTree::leaf(1), BinaryTree::leaf(9)).unwrap();
+        assert_eq!(Ok(()), bt.validate());
+        assert!(bt.contains(&9));
+    }
+
+    #[test]
+    #[should_panic(expected = "left subtree holds a value not less than the root")]
+    fn left_panics_when_out_of_order() {
+        BinaryTree::left(1, BinaryTree::leaf(2));
+    }
+
+    #[test]
+    fn validate_accepts_search_trees() {
+        assert_eq!(Ok(()), BinaryTree::<u32>::new().validate());
+        assert_eq!(Ok(()), unchecked::branch(5, unchecked::right(1, BinaryTree::leaf(3)), BinaryTree::leaf(9)).validate());
+        let mut bt: BinaryTree<u32> = (0..100).map(|i| (i * 37) % 100).collect();
+        bt.remove(&50);
+        assert_eq!(Ok(()), bt.validate());
+    }
+
+    #[test]
+    fn validate_checks_whole_subtrees() {
+        // 7 is greater than its parent 1, but lies in the left subtree of 5.
+        let bt = unchecked::branch(5, unchecked::right(1, BinaryTree::leaf(7)), BinaryTree::leaf(9));
+        let violation = bt.validate().unwrap_err();
+        assert_eq!(vec![Side::Left, Side::Right], violation.path);
+        assert_eq!(ViolationKind::OutOfOrder, violation.kind);
+        assert_eq!("value out of order at root.left.right", violation.to_string());
+
+        let bt = unchecked::left(5, BinaryTree::leaf(5));
+        assert_eq!(vec![Side::Left], bt.validate().unwrap_err().path);
+    }
+
+    #[test]
+    fn validate_checks_sizes_and_length() {
+        let mut bt = BinaryTree::branch(2, BinaryTree::leaf(1), BinaryTree::leaf(3));
+        bt.root.as_mut().unwrap().right.as_mut().unwrap().size = 4;
+        let violation = bt.validate().unwrap_err();
+        assert_eq!(vec![Side::Right], violation.path);
+        assert_eq!(ViolationKind::WrongSize{ cached: 4, actual: 1 }, violation.kind);
+
+        let mut bt = BinaryTree::branch(2, BinaryTree::leaf(1), BinaryTree::leaf(3));
+        bt.len = 2;
+        let violation = bt.validate().unwrap_err();
+        assert!(violation.path.is_empty());
+        assert_eq!(ViolationKind::WrongLength{ len: 2, nodes: 3 }, violation.kind);
+    }
+
+    fn matches_btreeset<B : Balance>() {
+        for &seed in &[12345, 6789] {
+            let mut tree: SearchTree<u32, B> = SearchTree::new();
+            let mut expected = BTreeSet::new();
+            let mut seed: u32 = seed;
+            for _ in 0..5000 {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let value = (seed >> 16) % 500;
+                if seed & 1 == 0 {
+                    tree.insert(value);
+                    expected.insert(value);
+                } else {
+                    assert_eq!(expected.remove(&value), tree.remove(&value).is_some());
+                }
+            }
+            assert_eq!(Ok(()), tree.validate());
+            assert_eq!(expected.len(), tree.len());
+            assert!(tree.iter().eq(expected.iter()));
+        }
+    }
+
+    test_each_balance!(random_inserts_and_removes_match_btreeset, matches_btreeset);
+
+    fn odds_and_threes() -> (AvlTree<u32>, AvlTree<u32>) {
+        ((0..10).map(|i| i * 2 + 1).collect(), (0..7).map(|i| i * 3).collect())
+    }
+
+    #[test]
+    fn lazy_set_operations() {
+        let (odds, threes) = odds_and_threes();
+        assert_eq!(vec![0, 1, 3, 5, 6, 7, 9, 11, 12, 13, 15, 17, 18, 19], odds.union(&threes).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![3, 9, 15], odds.intersection(&threes).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![1, 5, 7, 11, 13, 17, 19], odds.difference(&threes).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![0, 6, 12, 18], threes.difference(&odds).cloned().collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 5, 6, 7, 11, 12, 13, 17, 18, 19],
+                   odds.symmetric_difference(&threes).cloned().collect::<Vec<_>>());
+        assert_eq!(0, odds.intersection(&AvlTree::new()).count());
+    }
+
+    #[test]
+    fn consuming_set_operations_are_balanced() {
+        let (odds, threes) = odds_and_threes();
+        let union = odds.into_union(threes);
+        assert_eq!(Ok(()), union.validate());
+        assert!(union.iter().cloned().eq(vec![0, 1, 3, 5, 6, 7, 9, 11, 12, 13, 15, 17, 18, 19]));
+
+        let (odds, threes) = odds_and_threes();
+        let intersection = odds.into_intersection(threes);
+        assert_eq!(Ok(()), intersection.validate());
+        assert!(intersection.iter().cloned().eq(vec![3, 9, 15]));
+
+        let (odds, threes) = odds_and_threes();
+        let difference = odds.into_difference(threes);
+        assert_eq!(Ok(()), difference.validate());
+        assert!(difference.iter().cloned().eq(vec![1, 5, 7, 11, 13, 17, 19]));
+
+        let (odds, threes) = odds_and_threes();
+        let symmetric = odds.into_symmetric_difference(threes);
+        assert_eq!(Ok(()), symmetric.validate());
+        assert_eq!(11, symmetric.len());
+
+        let big: BinaryTree<u32> = (0..1000).collect();
+        let union = big.into_union((500..1500).collect());
+        assert_eq!(11, height(&union.root));
+    }
+
+    #[test]
+    fn consuming_union_keeps_values_from_self() {
+        let ours: BinaryTree<Keyed> = vec![Keyed(1, "ours")].into_iter().collect();
+        let theirs: BinaryTree<Keyed> = vec![Keyed(1, "theirs"), Keyed(2, "theirs")].into_iter().collect();
+        let union = ours.into_union(theirs);
+        assert_eq!(vec!["ours", "theirs"], union.iter().map(|k| k.1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn subset_superset_and_disjoint() {
+        let small: BinaryTree<u32> = vec![2, 4].into_iter().collect();
+        let big: BinaryTree<u32> = (0..6).collect();
+        let other: BinaryTree<u32> = vec![7, 9].into_iter().collect();
+        assert!(small.is_subset(&big));
+        assert!(!big.is_subset(&small));
+        assert!(big.is_superset(&small));
+        assert!(small.is_disjoint(&other));
+        assert!(!small.is_disjoint(&big));
+        assert!(BinaryTree::new().is_subset(&small));
+    }
+
+    // Inserts 0, 2, 4, ... up to `len` values in a scrambled order, so the
+    // balanced trees end up with their bookkeeping in every state.
+    fn scrambled<B : Balance>(len: u32) -> SearchTree<u32, B> {
+        let mut tree = SearchTree::new();
+        for i in 0..len {
+            tree.insert((i * 37) % len * 2);
+        }
+        tree
+    }
+
+    fn split_and_append<B : Balance>() {
+        for &len in &[0, 1, 2, 3, 10, 100, 331] {
+            for key in 0..(len * 2 + 2) {
+                let mut lower = scrambled::<B>(len);
+                let mut upper = lower.split_off(&key);
+                assert_eq!(Ok(()), lower.validate());
+                assert_eq!(Ok(()), upper.validate());
+                assert!(lower.iter().cloned().eq((0..len).map(|i| i * 2).filter(|&i| i < key)));
+                assert!(upper.iter().cloned().eq((0..len).map(|i| i * 2).filter(|&i| i >= key)));
+
+                lower.append(&mut upper);
+                assert_eq!(Ok(()), lower.validate());
+                assert!(upper.is_empty());
+                assert_eq!(len as usize, lower.len());
+                assert!(lower.iter().cloned().eq((0..len).map(|i| i * 2)));
+            }
+        }
+
+        // Joining trees of very different heights, either way round.
+        let mut small: SearchTree<u32, B> = (0..3).collect();
+        small.append(&mut (10..1000).collect());
+        assert_eq!(Ok(()), small.validate());
+        assert!(small.iter().cloned().eq((0..3).chain(10..1000)));
+        let mut small: SearchTree<u32, B> = (2000..2003).collect();
+        small.append(&mut (0..1000).collect());
+        assert_eq!(Ok(()), small.validate());
+        assert!(small.iter().cloned().eq((0..1000).chain(2000..2003)));
+    }
+
+    test_each_balance!(split_off_and_append, split_and_append);
+
+    #[test]
+    fn split_off_keeps_the_comparator() {
+        let mut tree = BinaryTree::with_comparator(Reverse(Natural));
+        tree.extend(0..10);
+        let upper = tree.split_off(&4);
+        assert!(tree.iter().cloned().eq((5..10).rev()));
+        assert!(upper.iter().cloned().eq((0..5).rev()));
+    }
+
+    #[test]
+    fn overlapping_append_takes_values_from_other() {
+        let mut ours: AvlTree<Keyed> = vec![Keyed(1, "ours"), Keyed(3, "ours")].into_iter().collect();
+        let mut theirs: AvlTree<Keyed> = vec![Keyed(2, "theirs"), Keyed(3, "theirs")].into_iter().collect();
+        ours.append(&mut theirs);
+        assert_eq!(Ok(()), ours.validate());
+        assert!(theirs.is_empty());
+        assert_eq!(vec!["ours", "theirs", "theirs"], ours.iter().map(|k| k.1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retain_keeps_trees_valid() {
+        let mut bt: BinaryTree<u32> = BinaryTree::new();
+        bt.extend(0..100);
+        let shape: Vec<u32> = bt.preorder().cloned().collect();
+        bt.retain(|_| true);
+        assert!(bt.preorder().cloned().eq(shape));
+        bt.retain(|i| i % 3 == 0);
+        assert_eq!(Ok(()), bt.validate());
+        assert!(bt.iter().cloned().eq((0..34).map(|i| i * 3)));
+
+        let mut avl: AvlTree<u32> = (0..1000).collect();
+        avl.retain(|i| !(10..=990).contains(i));
+        assert_eq!(Ok(()), avl.validate());
+        assert_eq!(19, avl.len());
+
+        let mut rb: RedBlackTree<u32> = (0..1000).collect();
+        let mut visited = Vec::new();
+        rb.retain(|&i| {
+            visited.push(i);
+            i % 7 != 0
+        });
+        assert!(visited.into_iter().eq(0..1000));
+        assert_eq!(Ok(()), rb.validate());
+        assert_eq!(857, rb.len());
+        rb.retain(|_| false);
+        assert!(rb.is_empty());
+    }
+
     #[test]
     fn test_from_slice_is_searchable() {
         let mut arr = vec![];
@@ -202,8 +1622,8 @@ mod tests {
 
         println!("running binary search");
         for i in 0..80000 {
-            assert!(tree.contains(i * 2));
-            assert!(!tree.contains(i * 2 + 1));
+            assert!(tree.contains(&(i * 2)));
+            assert!(!tree.contains(&(i * 2 + 1)));
         }
         println!("done");
 
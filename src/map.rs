@@ -0,0 +1,490 @@
+//! A map from keys to values, stored in a `SearchTree` ordered by key.
+
+use std::borrow::Borrow;
+use std::mem;
+
+use iter;
+use Avl;
+use Balance;
+use RedBlack;
+use SearchTree;
+use Unbalanced;
+
+/// A map from keys to values, kept in key order in a binary search tree
+/// balanced by `B`.
+///
+/// Each entry is stored as a `(key, value)` pair and found by the same search
+/// and insertion code as `SearchTree`, comparing keys only.
+#[derive(Debug,PartialEq)]
+pub struct SearchTreeMap<K, V, B : Balance> {
+    tree: SearchTree<(K, V), B>,
+}
+
+/// A map on a plain binary search tree.
+pub type BinaryTreeMap<K, V> = SearchTreeMap<K, V, Unbalanced>;
+
+/// A map on an AVL tree.
+pub type AvlTreeMap<K, V> = SearchTreeMap<K, V, Avl>;
+
+/// A map on a red-black tree.
+pub type RedBlackTreeMap<K, V> = SearchTreeMap<K, V, RedBlack>;
+
+impl <K, V, B : Balance> SearchTreeMap<K, V, B> {
+    /// Makes a new, empty map.
+    pub fn new() -> SearchTreeMap<K, V, B> {
+        SearchTreeMap{ tree: SearchTree::new() }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter{ inner: self.tree.iter() }
+    }
+
+    /// Iterates over the keys in order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys{ inner: self.tree.iter() }
+    }
+
+    /// Iterates over the values in the order of their keys.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values{ inner: self.tree.iter() }
+    }
+}
+
+impl <K : Ord, V, B : Balance> SearchTreeMap<K, V, B> {
+    /// Inserts `value` under `key`, returning the value it replaced. The key
+    /// already in the map is kept.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let mut replaced = None;
+        self.tree.insert_by((key, value), |_, a, b| a.0.cmp(&b.0), |entry, (_, value)| {
+            replaced = Some(mem::replace(&mut entry.1, value));
+        });
+        replaced
+    }
+
+    /// Returns the value stored under `key`, which may be any borrowed form
+    /// of the map's keys.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+            where K : Borrow<Q>, Q : Ord + ?Sized {
+        self.tree.find_by(|_, entry| key.cmp(entry.0.borrow())).map(|entry| &entry.1)
+    }
+
+    /// Returns a mutable reference to the value stored under `key`.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+            where K : Borrow<Q>, Q : Ord + ?Sized {
+        self.tree.find_mut_by(|_, entry| key.cmp(entry.0.borrow())).map(|entry| &mut entry.1)
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+            where K : Borrow<Q>, Q : Ord + ?Sized {
+        self.get(key).is_some()
+    }
+
+    /// Removes the entry for `key`, returning its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+            where K : Borrow<Q>, Q : Ord + ?Sized {
+        self.tree.remove_by(|_, entry| key.cmp(entry.0.borrow())).map(|entry| entry.1)
+    }
+
+    /// Finds the entry for `key` so that it can be read, updated or filled
+    /// in. An occupied entry is changed where it was found, while filling in
+    /// a vacant one searches the tree for its place again.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, B> {
+        match self.tree.find_mut_or_self(|_, entry| key.cmp(&entry.0)) {
+            Ok(entry) => Entry::Occupied(OccupiedEntry{ entry }),
+            Err(tree) => Entry::Vacant(VacantEntry{ tree, key })
+        }
+    }
+}
+
+impl <K, V, B : Balance> Default for SearchTreeMap<K, V, B> {
+    fn default() -> SearchTreeMap<K, V, B> {
+        SearchTreeMap::new()
+    }
+}
+
+/// A view into a single entry of a `SearchTreeMap`, which may or may not be
+/// filled in. Made by `SearchTreeMap::entry`.
+pub enum Entry<'a, K : 'a, V : 'a, B : Balance + 'a> {
+    Vacant(VacantEntry<'a, K, V, B>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+/// An entry of a `SearchTreeMap` with no value yet.
+pub struct VacantEntry<'a, K : 'a, V : 'a, B : Balance + 'a> {
+    tree: &'a mut SearchTree<(K, V), B>,
+    key: K,
+}
+
+/// An entry of a `SearchTreeMap` that holds a value.
+pub struct OccupiedEntry<'a, K : 'a, V : 'a> {
+    entry: &'a mut (K, V),
+}
+
+impl <'a, K : Ord, V, B : Balance> Entry<'a, K, V, B> {
+    /// Returns the entry's key.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Vacant(ref entry) => entry.key(),
+            Entry::Occupied(ref entry) => entry.key()
+        }
+    }
+
+    /// Fills in a vacant entry with `default`, then returns the entry's value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default),
+            Entry::Occupied(entry) => entry.into_mut()
+        }
+    }
+
+    /// Fills in a vacant entry with the result of `default`, then returns the
+    /// entry's value.
+    pub fn or_insert_with<F : FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut()
+        }
+    }
+
+    /// Calls `f` on the value of an occupied entry, leaving vacant entries
+    /// alone.
+    pub fn and_modify<F : FnOnce(&mut V)>(self, f: F) -> Entry<'a, K, V, B> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry)
+        }
+    }
+}
+
+impl <'a, K : Ord, V : Default, B : Balance> Entry<'a, K, V, B> {
+    /// Fills in a vacant entry with `V::default()`, then returns the entry's
+    /// value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl <'a, K : Ord, V, B : Balance> VacantEntry<'a, K, V, B> {
+    /// Returns the key the entry would be stored under.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Gives back the key without filling in the entry.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Fills in the entry with `value`, returning a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let rank = self.tree.insert_by((self.key, value), |_, a, b| a.0.cmp(&b.0), |_, _| {})
+            .expect("vacant entry was already filled in");
+        &mut self.tree.select_mut(rank).unwrap().1
+    }
+}
+
+impl <'a, K, V> OccupiedEntry<'a, K, V> {
+    /// Returns the key stored in the map.
+    pub fn key(&self) -> &K {
+        &self.entry.0
+    }
+
+    /// Returns the entry's value.
+    pub fn get(&self) -> &V {
+        &self.entry.1
+    }
+
+    /// Returns the entry's value for modification.
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.entry.1
+    }
+
+    /// Turns the entry into a reference to its value that lives as long as
+    /// the borrow of the map.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.entry.1
+    }
+
+    /// Replaces the entry's value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(&mut self.entry.1, value)
+    }
+}
+
+/// In-order iterator over the entries of a `SearchTreeMap`.
+pub struct Iter<'a, K : 'a, V : 'a> {
+    inner: iter::Iter<'a, (K, V)>,
+}
+
+impl <'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|entry| (&entry.0, &entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl <'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back().map(|entry| (&entry.0, &entry.1))
+    }
+}
+
+impl <'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+/// In-order iterator over the keys of a `SearchTreeMap`.
+pub struct Keys<'a, K : 'a, V : 'a> {
+    inner: iter::Iter<'a, (K, V)>,
+}
+
+impl <'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|entry| &entry.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl <'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back().map(|entry| &entry.0)
+    }
+}
+
+impl <'a, K, V> ExactSizeIterator for Keys<'a, K, V> {}
+
+/// Iterator over the values of a `SearchTreeMap`, in the order of their
+/// keys.
+pub struct Values<'a, K : 'a, V : 'a> {
+    inner: iter::Iter<'a, (K, V)>,
+}
+
+impl <'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|entry| &entry.1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl <'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back().map(|entry| &entry.1)
+    }
+}
+
+impl <'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
+
+/// Owning iterator over the entries of a `SearchTreeMap`, in key order.
+pub struct IntoIter<K, V> {
+    inner: iter::IntoIter<(K, V)>,
+}
+
+impl <K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl <K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl <'a, K, V, B : Balance> IntoIterator for &'a SearchTreeMap<K, V, B> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl <K, V, B : Balance> IntoIterator for SearchTreeMap<K, V, B> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter{ inner: self.tree.into_iter() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use map::{AvlTreeMap, BinaryTreeMap, Entry, RedBlackTreeMap, SearchTreeMap};
+    use Balance;
+
+    #[test]
+    fn insert_and_get() {
+        let mut map = BinaryTreeMap::new();
+        assert_eq!(None, map.insert(2, "two"));
+        assert_eq!(None, map.insert(1, "one"));
+        assert_eq!(None, map.insert(3, "three"));
+        assert_eq!(3, map.len());
+        assert_eq!(Some(&"one"), map.get(&1));
+        assert_eq!(Some(&"three"), map.get(&3));
+        assert_eq!(None, map.get(&4));
+        assert!(map.contains_key(&2));
+        assert!(!map.contains_key(&0));
+    }
+
+    #[test]
+    fn insert_replaces_value() {
+        let mut map = BinaryTreeMap::new();
+        map.insert("a", 1);
+        assert_eq!(Some(1), map.insert("a", 2));
+        assert_eq!(1, map.len());
+        assert_eq!(Some(&2), map.get(&"a"));
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut map = BinaryTreeMap::new();
+        map.insert(1, vec![1]);
+        map.get_mut(&1).unwrap().push(2);
+        assert_eq!(Some(&vec![1, 2]), map.get(&1));
+        assert_eq!(None, map.get_mut(&2));
+    }
+
+    #[test]
+    fn lookups_take_borrowed_keys() {
+        let mut map = BinaryTreeMap::new();
+        map.insert("one".to_string(), 1);
+        map.insert("two".to_string(), 2);
+        assert_eq!(Some(&1), map.get("one"));
+        assert!(map.contains_key("two"));
+        *map.get_mut("two").unwrap() += 10;
+        assert_eq!(Some(12), map.remove("two"));
+        assert!(!map.contains_key("two"));
+    }
+
+    #[test]
+    fn remove() {
+        let mut map = BinaryTreeMap::new();
+        for i in 0..10 {
+            map.insert(i, i * 10);
+        }
+        assert_eq!(Some(50), map.remove(&5));
+        assert_eq!(None, map.remove(&5));
+        assert_eq!(9, map.len());
+        assert!(!map.contains_key(&5));
+    }
+
+    #[test]
+    fn iterators_follow_key_order() {
+        let mut map = BinaryTreeMap::new();
+        map.insert("b", 2);
+        map.insert("c", 3);
+        map.insert("a", 1);
+        assert_eq!(vec![(&"a", &1), (&"b", &2), (&"c", &3)], map.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&"a", &"b", &"c"], map.keys().collect::<Vec<_>>());
+        assert_eq!(vec![&3, &2, &1], map.values().rev().collect::<Vec<_>>());
+        assert_eq!(vec![("a", 1), ("b", 2), ("c", 3)], map.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn balanced_maps() {
+        let mut avl = AvlTreeMap::new();
+        let mut rb = RedBlackTreeMap::new();
+        for i in 0..1000 {
+            avl.insert(i, i);
+            rb.insert(i, i);
+        }
+        for i in 0..500 {
+            avl.remove(&(i * 2));
+            rb.remove(&(i * 2));
+        }
+        assert!(avl.iter().eq(rb.iter()));
+        assert_eq!(Some(&999), avl.get(&999));
+        assert_eq!(None, rb.get(&998));
+    }
+
+    #[test]
+    fn entry_counts_words() {
+        let mut counts = BinaryTreeMap::new();
+        for word in "the cat saw the other cat and the dog".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(Some(&3), counts.get(&"the"));
+        assert_eq!(Some(&2), counts.get(&"cat"));
+        assert_eq!(Some(&1), counts.get(&"dog"));
+        assert_eq!(6, counts.len());
+    }
+
+    #[test]
+    fn entry_occupied_and_vacant() {
+        let mut map = BinaryTreeMap::new();
+        map.insert(1, String::from("one"));
+        match map.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(&1, entry.key());
+                assert_eq!("one", entry.get());
+                assert_eq!("one", entry.insert(String::from("uno")));
+            },
+            Entry::Vacant(_) => panic!("expected an occupied entry")
+        }
+        match map.entry(2) {
+            Entry::Vacant(entry) => {
+                assert_eq!(&2, entry.key());
+                entry.insert(String::from("dos"));
+            },
+            Entry::Occupied(_) => panic!("expected a vacant entry")
+        }
+        assert_eq!(Some(&String::from("uno")), map.get(&1));
+        assert_eq!(Some(&String::from("dos")), map.get(&2));
+    }
+
+    #[test]
+    fn entry_and_modify_or_default() {
+        let mut map: BinaryTreeMap<&str, Vec<i32>> = BinaryTreeMap::new();
+        map.entry("a").and_modify(|v| v.push(1)).or_default().push(2);
+        map.entry("a").and_modify(|v| v.push(3)).or_default().push(4);
+        let calls = ::std::cell::Cell::new(0);
+        map.entry("a").or_insert_with(|| { calls.set(calls.get() + 1); vec![] });
+        assert_eq!(0, calls.get());
+        assert_eq!(Some(&vec![2, 3, 4]), map.get(&"a"));
+    }
+
+    fn fill_through_entries<B : Balance>() {
+        let mut map: SearchTreeMap<u32, u32, B> = SearchTreeMap::new();
+        for i in 0..2000 {
+            let key = (i * 7919) % 1000;
+            *map.entry(key).or_insert(key * 10) += 1;
+        }
+        assert_eq!(1000, map.len());
+        assert!(map.iter().all(|(k, v)| *v == k * 10 + 2));
+    }
+
+    test_each_balance!(entries_on_balanced_maps, fill_through_entries);
+}
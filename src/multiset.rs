@@ -0,0 +1,292 @@
+//! A multiset, which keeps every copy of equal values, stored in a
+//! `SearchTree` ordered by value.
+
+use std::borrow::Borrow;
+
+use iter;
+use Avl;
+use Balance;
+use RedBlack;
+use SearchTree;
+use Unbalanced;
+
+/// A sorted bag of values, kept in order in a binary search tree balanced by
+/// `B`.
+///
+/// Equal values share a single node holding the value first inserted and
+/// the number of copies, so duplicates cost no extra space or depth.
+#[derive(Debug,PartialEq)]
+pub struct SearchTreeMultiSet<T, B : Balance> {
+    tree: SearchTree<(T, usize), B>,
+    len: usize,
+}
+
+/// A multiset on a plain binary search tree.
+pub type BinaryTreeMultiSet<T> = SearchTreeMultiSet<T, Unbalanced>;
+
+/// A multiset on an AVL tree.
+pub type AvlTreeMultiSet<T> = SearchTreeMultiSet<T, Avl>;
+
+/// A multiset on a red-black tree.
+pub type RedBlackTreeMultiSet<T> = SearchTreeMultiSet<T, RedBlack>;
+
+impl <T, B : Balance> SearchTreeMultiSet<T, B> {
+    /// Makes a new, empty multiset.
+    pub fn new() -> SearchTreeMultiSet<T, B> {
+        SearchTreeMultiSet{ tree: SearchTree::new(), len: 0 }
+    }
+
+    /// Returns the number of values in the multiset, counting duplicates.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of distinct values in the multiset.
+    pub fn distinct_len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the multiset contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over the values in order, yielding each duplicate.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter{ inner: self.tree.iter(), front: None, back: None, remaining: self.len }
+    }
+
+    /// Iterates over the distinct values in order, along with how many
+    /// copies of each the multiset holds.
+    pub fn counts(&self) -> Counts<'_, T> {
+        Counts{ inner: self.tree.iter() }
+    }
+}
+
+impl <T : Ord, B : Balance> SearchTreeMultiSet<T, B> {
+    /// Adds a copy of `value`. If an equal value is already present only its
+    /// count goes up, and `value` is dropped.
+    pub fn insert(&mut self, value: T) {
+        self.tree.insert_by((value, 1), |_, a, b| a.0.cmp(&b.0), |entry, _| entry.1 += 1);
+        self.len += 1;
+    }
+
+    /// Returns the number of copies of `value` in the multiset.
+    pub fn count<Q>(&self, value: &Q) -> usize
+            where T : Borrow<Q>, Q : Ord + ?Sized {
+        self.tree.find_by(|_, entry| value.cmp(entry.0.borrow())).map_or(0, |entry| entry.1)
+    }
+
+    /// Returns `true` if the multiset holds at least one copy of `value`.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+            where T : Borrow<Q>, Q : Ord + ?Sized {
+        self.count(value) > 0
+    }
+
+    /// Removes a single copy of `value`, returning `true` if there was one.
+    pub fn remove_one<Q>(&mut self, value: &Q) -> bool
+            where T : Borrow<Q>, Q : Ord + ?Sized {
+        let last = match self.tree.find_mut_by(|_, entry| value.cmp(entry.0.borrow())) {
+            Some(entry) if entry.1 > 1 => {
+                entry.1 -= 1;
+                false
+            },
+            Some(_) => true,
+            None => return false
+        };
+        if last {
+            self.tree.remove_by(|_, entry| value.cmp(entry.0.borrow()));
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every copy of `value`, returning how many there were.
+    pub fn remove_all<Q>(&mut self, value: &Q) -> usize
+            where T : Borrow<Q>, Q : Ord + ?Sized {
+        let removed = self.tree.remove_by(|_, entry| value.cmp(entry.0.borrow())).map_or(0, |entry| entry.1);
+        self.len -= removed;
+        removed
+    }
+}
+
+impl <T, B : Balance> Default for SearchTreeMultiSet<T, B> {
+    fn default() -> SearchTreeMultiSet<T, B> {
+        SearchTreeMultiSet::new()
+    }
+}
+
+/// In-order iterator over the values of a `SearchTreeMultiSet`, yielding
+/// each duplicate.
+pub struct Iter<'a, T : 'a> {
+    inner: iter::Iter<'a, (T, usize)>,
+    // A value being repeated at either end, with the copies left to yield.
+    front: Option<(&'a T, usize)>,
+    back: Option<(&'a T, usize)>,
+    remaining: usize,
+}
+
+impl <'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.front.is_none_or(|(_, left)| left == 0) {
+            // Once the tree runs out, the back end may still be part way
+            // through the last value.
+            self.front = match self.inner.next() {
+                Some(entry) => Some((&entry.0, entry.1)),
+                None => self.back.take()
+            };
+        }
+        self.remaining -= 1;
+        let front = self.front.as_mut().unwrap();
+        front.1 -= 1;
+        Some(front.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.back.is_none_or(|(_, left)| left == 0) {
+            self.back = match self.inner.next_back() {
+                Some(entry) => Some((&entry.0, entry.1)),
+                None => self.front.take()
+            };
+        }
+        self.remaining -= 1;
+        let back = self.back.as_mut().unwrap();
+        back.1 -= 1;
+        Some(back.0)
+    }
+}
+
+impl <'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// In-order iterator over the distinct values of a `SearchTreeMultiSet` and
+/// their counts.
+pub struct Counts<'a, T : 'a> {
+    inner: iter::Iter<'a, (T, usize)>,
+}
+
+impl <'a, T> Iterator for Counts<'a, T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<(&'a T, usize)> {
+        self.inner.next().map(|entry| (&entry.0, entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Counts<'a, T> {
+    fn next_back(&mut self) -> Option<(&'a T, usize)> {
+        self.inner.next_back().map(|entry| (&entry.0, entry.1))
+    }
+}
+
+impl <'a, T> ExactSizeIterator for Counts<'a, T> {}
+
+impl <'a, T, B : Balance> IntoIterator for &'a SearchTreeMultiSet<T, B> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use multiset::{BinaryTreeMultiSet, SearchTreeMultiSet};
+    use Balance;
+
+    #[test]
+    fn insert_counts_duplicates() {
+        let mut bag = BinaryTreeMultiSet::new();
+        for value in &[3, 1, 3, 2, 3, 1] {
+            bag.insert(*value);
+        }
+        assert_eq!(6, bag.len());
+        assert_eq!(3, bag.distinct_len());
+        assert_eq!(2, bag.count(&1));
+        assert_eq!(1, bag.count(&2));
+        assert_eq!(3, bag.count(&3));
+        assert_eq!(0, bag.count(&4));
+        assert!(bag.contains(&2));
+        assert!(!bag.contains(&4));
+    }
+
+    #[test]
+    fn iter_yields_each_duplicate() {
+        let mut bag = BinaryTreeMultiSet::new();
+        for value in &[2, 1, 2, 3, 2] {
+            bag.insert(*value);
+        }
+        assert_eq!(vec![&1, &2, &2, &2, &3], bag.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&3, &2, &2, &2, &1], bag.iter().rev().collect::<Vec<_>>());
+        assert_eq!(vec![(&1, 1), (&2, 3), (&3, 1)], bag.counts().collect::<Vec<_>>());
+        assert_eq!(5, bag.iter().len());
+    }
+
+    #[test]
+    fn iter_from_both_ends_meets_in_a_run() {
+        let mut bag = BinaryTreeMultiSet::new();
+        for _ in 0..4 {
+            bag.insert('a');
+        }
+        let mut iter = bag.iter();
+        assert_eq!(Some(&'a'), iter.next());
+        assert_eq!(Some(&'a'), iter.next_back());
+        assert_eq!(Some(&'a'), iter.next());
+        assert_eq!(Some(&'a'), iter.next_back());
+        assert_eq!(None, iter.next());
+        assert_eq!(None, iter.next_back());
+    }
+
+    #[test]
+    fn remove_one_and_remove_all() {
+        let mut bag = BinaryTreeMultiSet::new();
+        for value in &[5, 5, 5, 7] {
+            bag.insert(*value);
+        }
+        assert!(bag.remove_one(&5));
+        assert_eq!(2, bag.count(&5));
+        assert!(bag.remove_one(&7));
+        assert!(!bag.contains(&7));
+        assert!(!bag.remove_one(&7));
+        assert_eq!(1, bag.distinct_len());
+        assert_eq!(2, bag.remove_all(&5));
+        assert_eq!(0, bag.remove_all(&5));
+        assert!(bag.is_empty());
+    }
+
+    fn histogram<B : Balance>() {
+        let mut bag: SearchTreeMultiSet<u32, B> = SearchTreeMultiSet::new();
+        for i in 0..1000 {
+            bag.insert(i % 10);
+        }
+        for i in 0..10 {
+            assert_eq!(100, bag.count(&i));
+        }
+        for i in 0..5 {
+            assert_eq!(100, bag.remove_all(&(i * 2)));
+        }
+        assert_eq!(500, bag.len());
+        assert!(bag.iter().cloned().eq((0..5).flat_map(|i| vec![i * 2 + 1; 100])));
+    }
+
+    test_each_balance!(balanced_multisets, histogram);
+}
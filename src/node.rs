@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+
+pub type Link<T> = Option<Box<Node<T>>>;
+
+// Only the tests compare nodes, to check the shapes of small trees. Trees
+// themselves are compared by their values.
+#[cfg_attr(test, derive(Debug,PartialEq))]
+pub struct Node<T> {
+    pub(crate) data: T,
+    pub(crate) left: Link<T>,
+    pub(crate) right: Link<T>,
+    // Bookkeeping owned by the tree's `Balance` strategy: the height of this
+    // subtree under `Avl`, its colour under `RedBlack`, unused when
+    // `Unbalanced`.
+    pub(crate) balance: u8,
+    // The number of nodes in this subtree, this one included.
+    pub(crate) size: usize,
+}
+
+// The number of nodes below `link`.
+pub(crate) fn size<T>(link: &Link<T>) -> usize {
+    match *link {
+        Some(ref node) => node.size,
+        None => 0
+    }
+}
+
+impl <T> Node<T> {
+    pub(crate) fn leaf(data: T) -> Node<T> {
+        Node{ data, left: None, right: None, balance: 0, size: 1 }
+    }
+
+    pub(crate) fn branch(data: T, left: Link<T>, right: Link<T>) -> Node<T> {
+        let size = 1 + size(&left) + size(&right);
+        Node{ data, left, right, balance: 0, size }
+    }
+
+    // Recounts this subtree from its children's sizes.
+    pub(crate) fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    // Lifts the right child into this node's place. Sizes are kept up to
+    // date; the balance bookkeeping is left to the caller.
+    pub(crate) fn rotate_left(mut self: Box<Self>) -> Box<Node<T>> {
+        let mut right = self.right.take().expect("rotating left without a right child");
+        self.right = right.left.take();
+        self.update_size();
+        right.left = Some(self);
+        right.update_size();
+        right
+    }
+
+    // Lifts the left child into this node's place. Sizes are kept up to
+    // date; the balance bookkeeping is left to the caller.
+    pub(crate) fn rotate_right(mut self: Box<Self>) -> Box<Node<T>> {
+        let mut left = self.left.take().expect("rotating right without a left child");
+        self.left = left.right.take();
+        self.update_size();
+        left.right = Some(self);
+        left.update_size();
+        left
+    }
+
+    // Finds the node `probe` matches. `probe` orders the value being looked
+    // for against a node's data.
+    pub(crate) fn find<F : Fn(&T) -> Ordering>(&self, probe: &F) -> Option<&Node<T>> {
+        let mut node = self;
+        loop {
+            let next = match probe(&node.data) {
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => &node.right,
+                Ordering::Less => &node.left
+            };
+            node = next.as_ref()?;
+        }
+    }
+
+    pub(crate) fn find_mut<F : Fn(&T) -> Ordering>(&mut self, probe: &F) -> Option<&mut Node<T>> {
+        let mut node = self;
+        loop {
+            let next = match probe(&node.data) {
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => &mut node.right,
+                Ordering::Less => &mut node.left
+            };
+            node = next.as_mut()?;
+        }
+    }
+
+    // Follows `probe` down from `link`, growing or shrinking the size of
+    // every node on the way by one, and returns the link it stops at: the
+    // node `probe` matches, or the empty link where that node would go. Also
+    // counts the values below `link` that come before the subtree it stops
+    // at, which for an empty link is the rank a node put there would have.
+    fn walk<'a, F>(mut link: &'a mut Link<T>, probe: &mut F, grow: bool) -> (&'a mut Link<T>, usize)
+            where F : FnMut(&Node<T>) -> Ordering {
+        let mut rank = 0;
+        loop {
+            let ordering = match *link {
+                Some(ref node) => probe(node),
+                None => return (link, rank)
+            };
+            let node = match ordering {
+                Ordering::Equal => return (link, rank),
+                _ => link.as_mut().unwrap()
+            };
+            if grow {
+                node.size += 1;
+            } else {
+                node.size -= 1;
+            }
+            link = if ordering == Ordering::Less {
+                &mut node.left
+            } else {
+                rank += size(&node.left) + 1;
+                &mut node.right
+            };
+        }
+    }
+
+    // Inserts `value` where `order` places it, returning its rank. If an
+    // equal value is already in the tree, both are handed to `on_equal`
+    // instead.
+    pub(crate) fn insert<F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+            where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+        let mut probe = |node: &Node<T>| order(&value, &node.data);
+
+        // Sizes are grown on the way down, and shrunk back again on the rare
+        // occasion that nothing is added.
+        let (slot, rank) = Node::walk(link, &mut probe, true);
+        if slot.is_none() {
+            *slot = Some(Box::new(Node::leaf(value)));
+            return Some(rank);
+        }
+
+        let (slot, _) = Node::walk(link, &mut probe, false);
+        on_equal(&mut slot.as_mut().unwrap().data, value);
+        None
+    }
+
+    pub(crate) fn remove<F : FnMut(&Node<T>) -> Ordering>(link: &mut Link<T>, probe: &mut F) -> Option<T> {
+        let (slot, _) = Node::walk(link, probe, false);
+        if slot.is_none() {
+            Node::walk(link, probe, true);
+            return None;
+        }
+
+        let node = slot.as_mut().unwrap();
+        if node.left.is_some() && node.right.is_some() {
+            // Two children: the in-order successor takes this node's place.
+            node.size -= 1;
+            let successor = Node::remove_min(&mut node.right).unwrap();
+            return Some(std::mem::replace(&mut node.data, successor));
+        }
+
+        let mut node = slot.take().unwrap();
+        *slot = node.left.take().or_else(|| node.right.take());
+        Some(node.data)
+    }
+
+    pub(crate) fn remove_min(mut link: &mut Link<T>) -> Option<T> {
+        if link.is_none() {
+            return None;
+        }
+        while link.as_ref().unwrap().left.is_some() {
+            let node = link.as_mut().unwrap();
+            node.size -= 1;
+            link = &mut node.left;
+        }
+
+        let mut node = link.take().unwrap();
+        *link = node.right.take();
+        Some(node.data)
+    }
+}
+
+// Drops every node below `link` without recursing, which a long chain of
+// nodes would overflow the stack doing. Left children are rotated up until
+// the top node has none, and it is then dropped by itself.
+pub(crate) fn tear_down<T>(mut link: Link<T>) {
+    while let Some(mut node) = link {
+        link = match node.left.take() {
+            Some(mut left) => {
+                node.left = left.right.take();
+                left.right = Some(node);
+                Some(left)
+            },
+            None => node.right.take()
+        };
+    }
+}
+
+impl <T> Node<T> {
+    // Builds a tree out of the first `len` values `data` yields, which must
+    // be sorted, with all its leaves on its last two levels. Its bookkeeping
+    // is left to `Balance::build`.
+    pub(crate) fn from_sorted_iter<I : Iterator<Item = T>>(mut data: I, len: usize) -> Link<T> {
+        // Each run of values is split at its midpoint, and the left half
+        // built, the midpoint taken and the right half built in that order,
+        // so that values are taken in the order they come. The two halves
+        // are then joined under the midpoint. An explicit stack stands in
+        // for the recursion.
+        enum Step {
+            Build(usize),
+            Take,
+            Join,
+        }
+
+        let mut steps = vec![Step::Build(len)];
+        let mut taken = Vec::new();
+        let mut built: Vec<Link<T>> = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Build(0) => built.push(None),
+                Step::Build(len) => {
+                    // integer division by 2
+                    let pivot = len >> 1;
+                    steps.push(Step::Join);
+                    steps.push(Step::Build(len - pivot - 1));
+                    steps.push(Step::Take);
+                    steps.push(Step::Build(pivot));
+                },
+                Step::Take => taken.push(data.next().expect("fewer sorted values than promised")),
+                Step::Join => {
+                    let right = built.pop().unwrap();
+                    let left = built.pop().unwrap();
+                    built.push(Some(Box::new(Node::branch(taken.pop().unwrap(), left, right))));
+                }
+            }
+        }
+        built.pop().unwrap()
+    }
+}
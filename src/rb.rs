@@ -0,0 +1,509 @@
+use std::cmp::Ordering;
+
+use balance::sealed::Sealed;
+use error::{InvariantViolation, Side, ViolationKind};
+use node;
+use Balance;
+use Link;
+use Node;
+
+const BLACK: u8 = 0;
+const RED: u8 = 1;
+
+/// Rebalances as a red-black tree: no red node has a red child and every
+/// path down from a node passes the same number of black nodes. Writes need
+/// fewer rotations than under `Avl`, at the cost of a slightly taller tree.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct RedBlack;
+
+impl Balance for RedBlack {}
+
+impl Sealed for RedBlack {
+    fn insert<T, F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+            where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+        let added = insert(link, value, order, on_equal);
+        blacken_root(link);
+        added
+    }
+
+    fn remove<T, F>(link: &mut Link<T>, probe: &mut F) -> Option<T>
+            where F : FnMut(&Node<T>) -> Ordering {
+        let removed = remove(link, probe).map(|(value, _)| value);
+        blacken_root(link);
+        removed
+    }
+
+    fn build<T>(link: &mut Link<T>, len: usize) {
+        // Colouring the last level red, when it isn't also the root, gives
+        // every path the same number of black nodes.
+        let height = (0usize.leading_zeros() - len.leading_zeros()) as usize;
+        paint(link, 0, if height > 1 { height - 1 } else { height });
+    }
+
+    fn join<T>(mut left: Link<T>, mut middle: Box<Node<T>>, mut right: Link<T>) -> Box<Node<T>> {
+        // Blackening both roots keeps each tree valid and means `middle` can
+        // always go in red.
+        blacken_root(&mut left);
+        blacken_root(&mut right);
+        let (left_black, right_black) = (black_height(&left), black_height(&right));
+        let mut root = if left_black > right_black {
+            join_right(left, left_black, middle, right, right_black)
+        } else if right_black > left_black {
+            join_left(left, left_black, middle, right, right_black)
+        } else {
+            middle.left = left;
+            middle.right = right;
+            middle.update_size();
+            middle
+        };
+        root.balance = BLACK;
+        root
+    }
+
+    fn validate<T>(link: &Link<T>, path: &mut Vec<Side>) -> Result<(), InvariantViolation> {
+        if is_red(link) {
+            return Err(InvariantViolation{ path: path.clone(), kind: ViolationKind::RedRoot });
+        }
+        check_colours(link, path).map(|_| ())
+    }
+}
+
+// Checks the colouring below `link`, returning the number of black nodes on
+// each path down from it.
+fn check_colours<T>(link: &Link<T>, path: &mut Vec<Side>) -> Result<usize, InvariantViolation> {
+    let node = match *link {
+        Some(ref node) => node,
+        None => return Ok(1)
+    };
+
+    let kind = if node.balance != RED && node.balance != BLACK {
+        Some(ViolationKind::BadColour(node.balance))
+    } else if node.balance == RED && (is_red(&node.left) || is_red(&node.right)) {
+        Some(ViolationKind::RedChild)
+    } else {
+        None
+    };
+    if let Some(kind) = kind {
+        return Err(InvariantViolation{ path: path.clone(), kind });
+    }
+
+    path.push(Side::Left);
+    let left = check_colours(&node.left, path)?;
+    path.pop();
+    path.push(Side::Right);
+    let right = check_colours(&node.right, path)?;
+    path.pop();
+
+    if left != right {
+        let kind = ViolationKind::BlackHeightsDiffer{ left, right };
+        return Err(InvariantViolation{ path: path.clone(), kind });
+    }
+    Ok(left + if node.balance == BLACK { 1 } else { 0 })
+}
+
+// The number of black nodes on each path down from `link`, counting the
+// empty link at the bottom.
+fn black_height<T>(mut link: &Link<T>) -> usize {
+    let mut black = 1;
+    while let Some(ref node) = *link {
+        if node.balance == BLACK {
+            black += 1;
+        }
+        link = &node.left;
+    }
+    black
+}
+
+// Hangs `middle`, red, with `right` to its right, somewhere down the right
+// edge of `left`, which has `left_black` black nodes on each path and at
+// least as many as `right`. A red node left with a red child on the way back
+// up is rotated away by its black parent.
+fn join_right<T>(left: Link<T>, left_black: usize, mut middle: Box<Node<T>>, right: Link<T>,
+                 right_black: usize) -> Box<Node<T>> {
+    if !is_red(&left) && left_black == right_black {
+        middle.left = left;
+        middle.right = right;
+        middle.balance = RED;
+        middle.update_size();
+        return middle;
+    }
+
+    let mut node = left.unwrap();
+    let child_black = if node.balance == BLACK { left_black - 1 } else { left_black };
+    node.right = Some(join_right(node.right.take(), child_black, middle, right, right_black));
+    node.update_size();
+
+    let broken = match node.right {
+        Some(ref child) => node.balance == BLACK && child.balance == RED && is_red(&child.right),
+        None => false
+    };
+    if broken {
+        set_colour(&mut node.right.as_mut().unwrap().right, BLACK);
+        return node.rotate_left();
+    }
+    node
+}
+
+// The mirror image of `join_right`, for a `right` with at least as many
+// black nodes on each path as `left`.
+fn join_left<T>(left: Link<T>, left_black: usize, mut middle: Box<Node<T>>, right: Link<T>,
+                right_black: usize) -> Box<Node<T>> {
+    if !is_red(&right) && left_black == right_black {
+        middle.left = left;
+        middle.right = right;
+        middle.balance = RED;
+        middle.update_size();
+        return middle;
+    }
+
+    let mut node = right.unwrap();
+    let child_black = if node.balance == BLACK { right_black - 1 } else { right_black };
+    node.left = Some(join_left(left, left_black, middle, node.left.take(), child_black));
+    node.update_size();
+
+    let broken = match node.left {
+        Some(ref child) => node.balance == BLACK && child.balance == RED && is_red(&child.left),
+        None => false
+    };
+    if broken {
+        set_colour(&mut node.left.as_mut().unwrap().left, BLACK);
+        return node.rotate_right();
+    }
+    node
+}
+
+fn paint<T>(link: &mut Link<T>, depth: usize, red_depth: usize) {
+    if let Some(ref mut node) = *link {
+        node.balance = if depth == red_depth { RED } else { BLACK };
+        paint(&mut node.left, depth + 1, red_depth);
+        paint(&mut node.right, depth + 1, red_depth);
+    }
+}
+
+fn is_red<T>(link: &Link<T>) -> bool {
+    match *link {
+        Some(ref node) => node.balance == RED,
+        None => false
+    }
+}
+
+fn set_colour<T>(link: &mut Link<T>, colour: u8) {
+    if let Some(ref mut node) = *link {
+        node.balance = colour;
+    }
+}
+
+fn blacken_root<T>(link: &mut Link<T>) {
+    set_colour(link, BLACK);
+}
+
+fn insert<T, F, E>(link: &mut Link<T>, value: T, order: &F, on_equal: E) -> Option<usize>
+        where F : Fn(&T, &T) -> Ordering, E : FnOnce(&mut T, T) {
+    let added = match *link {
+        Some(ref mut node) => match order(&value, &node.data) {
+            Ordering::Less => insert(&mut node.left, value, order, on_equal),
+            Ordering::Greater => insert(&mut node.right, value, order, on_equal)
+                .map(|rank| rank + node::size(&node.left) + 1),
+            Ordering::Equal => {
+                on_equal(&mut node.data, value);
+                None
+            }
+        },
+        None => {
+            let mut node = Node::leaf(value);
+            node.balance = RED;
+            *link = Some(Box::new(node));
+            return Some(0);
+        }
+    };
+
+    if added.is_some() {
+        let mut node = link.take().unwrap();
+        node.size += 1;
+        *link = Some(fix_red_child(node));
+    }
+    added
+}
+
+// Repairs a red child of `node` that has a red child of its own, which is
+// the only way an insertion below `node` can break the colouring. This may
+// leave `node` itself red, for its parent to deal with in turn.
+fn fix_red_child<T>(mut node: Box<Node<T>>) -> Box<Node<T>> {
+    let left_broken = match node.left {
+        Some(ref left) => left.balance == RED && (is_red(&left.left) || is_red(&left.right)),
+        None => false
+    };
+    let right_broken = match node.right {
+        Some(ref right) => right.balance == RED && (is_red(&right.left) || is_red(&right.right)),
+        None => false
+    };
+
+    if !left_broken && !right_broken {
+        return node;
+    }
+
+    if is_red(&node.left) && is_red(&node.right) {
+        // Push the redness up a level rather than rotating.
+        set_colour(&mut node.left, BLACK);
+        set_colour(&mut node.right, BLACK);
+        node.balance = RED;
+        return node;
+    }
+
+    let mut root = if left_broken {
+        let left = node.left.take().unwrap();
+        node.left = Some(if is_red(&left.right) { left.rotate_left() } else { left });
+        node.rotate_right()
+    } else {
+        let right = node.right.take().unwrap();
+        node.right = Some(if is_red(&right.left) { right.rotate_right() } else { right });
+        node.rotate_left()
+    };
+    root.balance = BLACK;
+    set_colour(&mut root.left, RED);
+    set_colour(&mut root.right, RED);
+    root
+}
+
+// Removes `value` from below `link`, also reporting whether the subtree lost
+// a black node from each of its paths.
+fn remove<T, F>(link: &mut Link<T>, probe: &mut F) -> Option<(T, bool)>
+        where F : FnMut(&Node<T>) -> Ordering {
+    let ordering = match *link {
+        Some(ref node) => probe(node),
+        None => return None
+    };
+
+    match ordering {
+        Ordering::Less => {
+            let (removed, shorter) = remove(&mut link.as_mut().unwrap().left, probe)?;
+            link.as_mut().unwrap().size -= 1;
+            Some((removed, shorter && fix_short_left(link)))
+        },
+        Ordering::Greater => {
+            let (removed, shorter) = remove(&mut link.as_mut().unwrap().right, probe)?;
+            link.as_mut().unwrap().size -= 1;
+            Some((removed, shorter && fix_short_right(link)))
+        },
+        Ordering::Equal => {
+            let has_both = {
+                let node = link.as_ref().unwrap();
+                node.left.is_some() && node.right.is_some()
+            };
+            if !has_both {
+                return Some(unlink(link));
+            }
+
+            let (successor, shorter) = remove_min(&mut link.as_mut().unwrap().right).unwrap();
+            let node = link.as_mut().unwrap();
+            node.size -= 1;
+            let removed = ::std::mem::replace(&mut node.data, successor);
+            Some((removed, shorter && fix_short_right(link)))
+        }
+    }
+}
+
+fn remove_min<T>(link: &mut Link<T>) -> Option<(T, bool)> {
+    let has_left = match *link {
+        Some(ref node) => node.left.is_some(),
+        None => return None
+    };
+
+    if !has_left {
+        return Some(unlink(link));
+    }
+
+    let (removed, shorter) = remove_min(&mut link.as_mut().unwrap().left)?;
+    link.as_mut().unwrap().size -= 1;
+    Some((removed, shorter && fix_short_left(link)))
+}
+
+// Removes a node with at most one child, which takes its place.
+fn unlink<T>(link: &mut Link<T>) -> (T, bool) {
+    let mut node = link.take().unwrap();
+    *link = node.left.take().or_else(|| node.right.take());
+
+    // A lone child is always red, so recolouring it makes up for a removed
+    // black node.
+    let shorter = if node.balance == RED {
+        false
+    } else if is_red(link) {
+        blacken_root(link);
+        false
+    } else {
+        true
+    };
+    (node.data, shorter)
+}
+
+// The left subtree of the node at `link` is a black node short. Rebalances,
+// returning `true` if the whole subtree is now a black node short instead.
+fn fix_short_left<T>(link: &mut Link<T>) -> bool {
+    let mut node = link.take().unwrap();
+
+    if is_red(&node.right) {
+        // Rotate the red sibling up, so the short side gets a black sibling
+        // and a red parent.
+        let mut root = node.rotate_left();
+        root.balance = BLACK;
+        set_colour(&mut root.left, RED);
+        fix_short_left(&mut root.left);
+        *link = Some(root);
+        return false;
+    }
+
+    let far_red = is_red(&node.right.as_ref().unwrap().right);
+    let near_red = is_red(&node.right.as_ref().unwrap().left);
+    if !far_red && !near_red {
+        set_colour(&mut node.right, RED);
+        let shorter = node.balance == BLACK;
+        node.balance = BLACK;
+        *link = Some(node);
+        return shorter;
+    }
+
+    if !far_red {
+        let sibling = node.right.take().unwrap();
+        let mut sibling = sibling.rotate_right();
+        sibling.balance = BLACK;
+        set_colour(&mut sibling.right, RED);
+        node.right = Some(sibling);
+    }
+
+    let colour = node.balance;
+    let mut root = node.rotate_left();
+    root.balance = colour;
+    set_colour(&mut root.left, BLACK);
+    set_colour(&mut root.right, BLACK);
+    *link = Some(root);
+    false
+}
+
+// The mirror image of `fix_short_left`.
+fn fix_short_right<T>(link: &mut Link<T>) -> bool {
+    let mut node = link.take().unwrap();
+
+    if is_red(&node.left) {
+        let mut root = node.rotate_right();
+        root.balance = BLACK;
+        set_colour(&mut root.right, RED);
+        fix_short_right(&mut root.right);
+        *link = Some(root);
+        return false;
+    }
+
+    let far_red = is_red(&node.left.as_ref().unwrap().left);
+    let near_red = is_red(&node.left.as_ref().unwrap().right);
+    if !far_red && !near_red {
+        set_colour(&mut node.left, RED);
+        let shorter = node.balance == BLACK;
+        node.balance = BLACK;
+        *link = Some(node);
+        return shorter;
+    }
+
+    if !far_red {
+        let sibling = node.left.take().unwrap();
+        let mut sibling = sibling.rotate_left();
+        sibling.balance = BLACK;
+        set_colour(&mut sibling.left, RED);
+        node.left = Some(sibling);
+    }
+
+    let colour = node.balance;
+    let mut root = node.rotate_right();
+    root.balance = colour;
+    set_colour(&mut root.left, BLACK);
+    set_colour(&mut root.right, BLACK);
+    *link = Some(root);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+
+    use rb::{BLACK, RED};
+    use Balance;
+    use RedBlackTree;
+    use SearchTree;
+    use {Side, ViolationKind};
+
+    #[test]
+    fn sorted_inserts_stay_valid() {
+        let mut tree = RedBlackTree::new();
+        for i in 0..1000 {
+            tree.insert(i);
+            assert_eq!(Ok(()), tree.validate());
+        }
+        assert_eq!(1000, tree.len());
+        assert!(tree.iter().cloned().eq(0..1000));
+    }
+
+    #[test]
+    fn removes_stay_valid() {
+        let mut tree = RedBlackTree::new();
+        for i in 0..1000 {
+            tree.insert(i);
+        }
+        for i in (0..1000).filter(|i| i % 3 != 0) {
+            assert_eq!(Some(i), tree.remove(&i));
+            assert_eq!(Ok(()), tree.validate());
+        }
+        assert_eq!(None, tree.remove(&1));
+        assert!(tree.iter().cloned().eq((0..1000).filter(|i| i % 3 == 0)));
+    }
+
+    #[test]
+    fn from_sorted_is_valid() {
+        for len in 0..100 {
+            let data: Vec<i32> = (0..len).collect();
+            let mut tree = RedBlackTree::from_sorted(&data);
+            assert_eq!(Ok(()), tree.validate());
+            tree.insert(len);
+            tree.remove(&0);
+            assert_eq!(Ok(()), tree.validate());
+        }
+    }
+
+    #[test]
+    fn validate_checks_colours() {
+        let mut tree = RedBlackTree::from_sorted(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(Ok(()), tree.validate());
+
+        tree.root.as_mut().unwrap().balance = RED;
+        assert_eq!(ViolationKind::RedRoot, tree.validate().unwrap_err().kind);
+        tree.root.as_mut().unwrap().balance = BLACK;
+
+        // The last level, 5 below 6, is red.
+        tree.root.as_mut().unwrap().right.as_mut().unwrap().balance = RED;
+        let violation = tree.validate().unwrap_err();
+        assert_eq!(vec![Side::Right], violation.path);
+        assert_eq!(ViolationKind::RedChild, violation.kind);
+
+        tree.root.as_mut().unwrap().right.as_mut().unwrap().balance = 7;
+        assert_eq!(ViolationKind::BadColour(7), tree.validate().unwrap_err().kind);
+
+        tree.root.as_mut().unwrap().right.as_mut().unwrap().balance = BLACK;
+        tree.root.as_mut().unwrap().right.as_mut().unwrap().left.as_mut().unwrap().balance = BLACK;
+        let violation = tree.validate().unwrap_err();
+        assert_eq!(vec![Side::Right], violation.path);
+        assert_eq!(ViolationKind::BlackHeightsDiffer{ left: 2, right: 1 }, violation.kind);
+    }
+
+    fn exercise<B : Balance>() {
+        let mut tree: SearchTree<u32, B> = SearchTree::new();
+        for i in 0..100 {
+            tree.insert((i * 37) % 100);
+        }
+        for i in 0..50 {
+            tree.remove(&(i * 2));
+        }
+        assert!(tree.contains(&99));
+        assert!(!tree.contains(&98));
+        assert_eq!(Some(&51), tree.select(25));
+        assert_eq!(25, tree.rank(&51));
+        assert!(tree.iter().cloned().eq((0..50).map(|i| i * 2 + 1)));
+    }
+
+    test_each_balance!(strategies_are_interchangeable, exercise);
+}
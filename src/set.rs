@@ -0,0 +1,141 @@
+//! Lazy set operations on two `SearchTree`s, which walk both trees in step.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::marker::PhantomData;
+
+use Compare;
+use Iter;
+
+// Walks two sorted sequences in step. Each call to `next` takes the smaller
+// of the two next values, or both of them when they are equal, and says
+// which sequence each came from.
+pub(crate) struct Merge<'c, T : ?Sized, I : Iterator, C : 'c> {
+    a: Peekable<I>,
+    b: Peekable<I>,
+    compare: &'c C,
+    values: PhantomData<fn(&T)>,
+}
+
+impl <'c, T : ?Sized, I, C> Merge<'c, T, I, C>
+        where I : Iterator, I::Item : Borrow<T>, C : Compare<T> {
+    pub(crate) fn new(a: I, b: I, compare: &'c C) -> Merge<'c, T, I, C> {
+        Merge{ a: a.peekable(), b: b.peekable(), compare, values: PhantomData }
+    }
+
+    // Returns `(None, None)` once both sequences are used up.
+    pub(crate) fn next(&mut self) -> (Option<I::Item>, Option<I::Item>) {
+        let ordering = match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) => self.compare.compare(a.borrow(), b.borrow()),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return (None, None)
+        };
+        match ordering {
+            Ordering::Less => (self.a.next(), None),
+            Ordering::Greater => (None, self.b.next()),
+            Ordering::Equal => (self.a.next(), self.b.next())
+        }
+    }
+}
+
+/// In-order iterator over the values in either of two `SearchTree`s. Made
+/// by `SearchTree::union`.
+pub struct Union<'a, T : 'a, C : 'a> {
+    merge: Merge<'a, T, Iter<'a, T>, C>,
+}
+
+/// In-order iterator over the values in both of two `SearchTree`s. Made by
+/// `SearchTree::intersection`.
+pub struct Intersection<'a, T : 'a, C : 'a> {
+    merge: Merge<'a, T, Iter<'a, T>, C>,
+}
+
+/// In-order iterator over the values in one `SearchTree` but not another.
+/// Made by `SearchTree::difference`.
+pub struct Difference<'a, T : 'a, C : 'a> {
+    merge: Merge<'a, T, Iter<'a, T>, C>,
+}
+
+/// In-order iterator over the values in exactly one of two `SearchTree`s.
+/// Made by `SearchTree::symmetric_difference`.
+pub struct SymmetricDifference<'a, T : 'a, C : 'a> {
+    merge: Merge<'a, T, Iter<'a, T>, C>,
+}
+
+impl <'a, T, C : Compare<T>> Union<'a, T, C> {
+    pub(crate) fn new(a: Iter<'a, T>, b: Iter<'a, T>, compare: &'a C) -> Union<'a, T, C> {
+        Union{ merge: Merge::new(a, b, compare) }
+    }
+}
+
+impl <'a, T, C : Compare<T>> Intersection<'a, T, C> {
+    pub(crate) fn new(a: Iter<'a, T>, b: Iter<'a, T>, compare: &'a C) -> Intersection<'a, T, C> {
+        Intersection{ merge: Merge::new(a, b, compare) }
+    }
+}
+
+impl <'a, T, C : Compare<T>> Difference<'a, T, C> {
+    pub(crate) fn new(a: Iter<'a, T>, b: Iter<'a, T>, compare: &'a C) -> Difference<'a, T, C> {
+        Difference{ merge: Merge::new(a, b, compare) }
+    }
+}
+
+impl <'a, T, C : Compare<T>> SymmetricDifference<'a, T, C> {
+    pub(crate) fn new(a: Iter<'a, T>, b: Iter<'a, T>, compare: &'a C) -> SymmetricDifference<'a, T, C> {
+        SymmetricDifference{ merge: Merge::new(a, b, compare) }
+    }
+}
+
+impl <'a, T, C : Compare<T>> Iterator for Union<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let (a, b) = self.merge.next();
+        a.or(b)
+    }
+}
+
+impl <'a, T, C : Compare<T>> Iterator for Intersection<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match self.merge.next() {
+                (Some(a), Some(_)) => return Some(a),
+                (None, None) => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl <'a, T, C : Compare<T>> Iterator for Difference<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match self.merge.next() {
+                (Some(a), None) => return Some(a),
+                (None, None) => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl <'a, T, C : Compare<T>> Iterator for SymmetricDifference<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match self.merge.next() {
+                (Some(a), None) => return Some(a),
+                (None, Some(b)) => return Some(b),
+                (None, None) => return None,
+                _ => {}
+            }
+        }
+    }
+}
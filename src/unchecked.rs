@@ -0,0 +1,29 @@
+//! Constructors that assemble a `BinaryTree` by hand without checking that
+//! its values are in order, for callers who already know they are.
+//!
+//! A tree whose left subtrees hold larger values or whose right subtrees
+//! hold smaller ones can't find its own values. `SearchTree::validate` can
+//! tell whether a tree made here is sound.
+
+use BinaryTree;
+use Natural;
+use Node;
+
+/// Makes a tree with `data` at its root over `left`, which should hold only
+/// values less than `data`.
+pub fn left<T>(data: T, left: BinaryTree<T>) -> BinaryTree<T> {
+    branch(data, left, BinaryTree::new())
+}
+
+/// Makes a tree with `data` at its root over `right`, which should hold only
+/// values greater than `data`.
+pub fn right<T>(data: T, right: BinaryTree<T>) -> BinaryTree<T> {
+    branch(data, BinaryTree::new(), right)
+}
+
+/// Makes a tree with `data` at its root over `left` and `right`, which should
+/// hold only values less and greater than `data` respectively.
+pub fn branch<T>(data: T, mut left: BinaryTree<T>, mut right: BinaryTree<T>) -> BinaryTree<T> {
+    let len = left.len + right.len + 1;
+    BinaryTree::with_root(Some(Box::new(Node::branch(data, left.root.take(), right.root.take()))), len, Natural)
+}